@@ -0,0 +1,333 @@
+use aoc2019::intcode::{
+    disassembler,
+    io::{FnOutput, IntcodeInput, IntcodeOutput},
+    load,
+    program::{Error, Program, Stopped, System, MAX_MEMORY_LEN},
+};
+use std::io::{self, BufRead, Write};
+use std::{env, process};
+
+/// How many instructions `rstep` can undo
+const HISTORY_DEPTH: usize = 100_000;
+
+const HELP: &str = "\
+Commands:
+  s, step [n]             execute n instructions (default 1)
+  rs, rstep [n]           undo n instructions (default 1)
+  c, continue             run until halt, breakpoint, watchpoint or input is needed
+  b, break <addr>         set a breakpoint
+  d, delete <addr>        remove a breakpoint
+  w, watch <addr>         stop after any write to addr
+  u, unwatch <addr>       remove a watchpoint
+  l, list [addr] [n]      disassemble n instructions from addr (default: the IP, 8)
+  m, mem <addr> [n]       dump n memory cells from addr (default 16)
+  wr, writer <addr>       show the last instruction that wrote to addr
+  i, input <value>...     queue input values
+  r, regs                 show the instruction pointer and relative base
+  h, help                 show this help
+  q, quit                 exit the debugger";
+
+#[derive(Debug, PartialEq)]
+enum Command {
+    Step(usize),
+    ReverseStep(usize),
+    Continue,
+    Break(usize),
+    Delete(usize),
+    Watch(usize),
+    Unwatch(usize),
+    List(Option<usize>, usize),
+    Mem(usize, usize),
+    Writer(usize),
+    Input(Vec<i64>),
+    Regs,
+    Help,
+    Quit,
+}
+
+impl Command {
+    fn parse(line: &str) -> Result<Self, String> {
+        let mut words = line.split_whitespace();
+        let name = words.next().unwrap_or("");
+        let args: Vec<&str> = words.collect();
+
+        let number = |index: usize| -> Result<Option<usize>, String> {
+            args.get(index)
+                .map(|arg| {
+                    arg.parse::<usize>()
+                        .map_err(|_| format!("'{}' is not a valid address or count", arg))
+                })
+                .transpose()
+        };
+        let address = || number(0)?.ok_or_else(|| format!("'{}' needs an address", name));
+
+        match name {
+            "s" | "step" => Ok(Command::Step(number(0)?.unwrap_or(1))),
+            "rs" | "rstep" => Ok(Command::ReverseStep(number(0)?.unwrap_or(1))),
+            "c" | "continue" => Ok(Command::Continue),
+            "b" | "break" => Ok(Command::Break(address()?)),
+            "d" | "delete" => Ok(Command::Delete(address()?)),
+            "w" | "watch" => Ok(Command::Watch(address()?)),
+            "u" | "unwatch" => Ok(Command::Unwatch(address()?)),
+            "l" | "list" => Ok(Command::List(number(0)?, number(1)?.unwrap_or(8))),
+            "m" | "mem" => Ok(Command::Mem(address()?, number(1)?.unwrap_or(16))),
+            "wr" | "writer" => Ok(Command::Writer(address()?)),
+            "i" | "input" => args
+                .iter()
+                .map(|arg| {
+                    arg.parse::<i64>()
+                        .map_err(|_| format!("'{}' is not a valid input value", arg))
+                })
+                .collect::<Result<Vec<_>, _>>()
+                .map(Command::Input),
+            "r" | "regs" => Ok(Command::Regs),
+            "h" | "help" => Ok(Command::Help),
+            "q" | "quit" => Ok(Command::Quit),
+            _ => Err(format!("Unknown command '{}', try 'help'", name)),
+        }
+    }
+}
+
+fn main() {
+    let path = match env::args().nth(1) {
+        Some(path) => path,
+        None => {
+            eprintln!("Usage: intcode-dbg <program-file>");
+            process::exit(2);
+        }
+    };
+
+    let memory = match load::from_file(&path) {
+        Ok(memory) => memory,
+        Err(load::Error::Io(e)) => {
+            eprintln!("Failed to read '{}': {}", path, e);
+            process::exit(1);
+        }
+        Err(e) => {
+            eprintln!("Failed to parse '{}': {}", path, e);
+            process::exit(1);
+        }
+    };
+
+    let mut program =
+        Program::with_channels(&memory, (), FnOutput(|value| println!("output: {}", value)));
+    program.set_history(Some(HISTORY_DEPTH));
+
+    println!(
+        "Loaded {} cells from '{}'. Type 'help' for commands.",
+        memory.len(),
+        path
+    );
+    print_current(&program);
+
+    let stdin = io::stdin();
+    loop {
+        let mut stdout = io::stdout();
+        if let Err(e) = write!(stdout, "(dbg) ").and_then(|_| stdout.flush()) {
+            eprintln!("Failed to write output: {}", e);
+            process::exit(1);
+        }
+
+        let mut line = String::new();
+        match stdin.lock().read_line(&mut line) {
+            Ok(0) => break,
+            Ok(_) => {}
+            Err(e) => {
+                eprintln!("Failed to read command: {}", e);
+                process::exit(1);
+            }
+        }
+        if line.trim().is_empty() {
+            continue;
+        }
+
+        match Command::parse(&line) {
+            Ok(Command::Quit) => break,
+            Ok(command) => execute(&mut program, command),
+            Err(message) => println!("{}", message),
+        }
+    }
+}
+
+fn execute<I, O>(program: &mut Program<I, O>, command: Command)
+where
+    I: IntcodeInput,
+    O: IntcodeOutput,
+{
+    match command {
+        Command::Step(count) => {
+            for _ in 0..count {
+                if !step(program) {
+                    break;
+                }
+            }
+            print_current(program);
+        }
+        Command::ReverseStep(count) => {
+            for _ in 0..count {
+                if program.step_back().is_none() {
+                    println!("No more history to undo");
+                    break;
+                }
+            }
+            print_current(program);
+        }
+        Command::Continue => {
+            let stopped = program.run().map(Some);
+            report(program, stopped);
+            print_current(program);
+        }
+        Command::Break(address) => program.add_breakpoint(address),
+        Command::Delete(address) => {
+            if !program.remove_breakpoint(address) {
+                println!("No breakpoint at {:04}", address);
+            }
+        }
+        Command::Watch(address) => program.add_watchpoint(address),
+        Command::Unwatch(address) => {
+            if !program.remove_watchpoint(address) {
+                println!("No watchpoint on {:04}", address);
+            }
+        }
+        Command::List(address, count) => {
+            let mut address = address.unwrap_or_else(|| program.read_instruction_pointer());
+            for _ in 0..count {
+                if address >= program.memory.len() {
+                    break;
+                }
+                let (line, size) = disassembler::disassemble_at_with(
+                    &program.memory,
+                    address,
+                    program.instruction_set(),
+                );
+                println!("{} {}", marker(program, address), line);
+                address += size;
+            }
+        }
+        Command::Mem(address, count) => {
+            let end = address.saturating_add(count).min(MAX_MEMORY_LEN);
+            for row in (address..end).step_by(8) {
+                let values: Vec<_> = (row..end.min(row + 8))
+                    .map(|a| match program.read_memory(a) {
+                        Ok(value) => format!("{:>8}", value),
+                        Err(_) => format!("{:>8}", "-"),
+                    })
+                    .collect();
+                println!("{:04}: {}", row, values.join(" "));
+            }
+        }
+        Command::Writer(address) => match program.last_writer(address) {
+            Some(writer) => {
+                let (line, _) = disassembler::disassemble_at_with(
+                    &program.memory,
+                    writer,
+                    program.instruction_set(),
+                );
+                println!("{}", line);
+            }
+            None => println!("No recorded instruction wrote to {:04}", address),
+        },
+        Command::Input(values) => values.into_iter().for_each(|v| program.push_input(v)),
+        Command::Regs => println!(
+            "ip = {:04}, rb = {}",
+            program.read_instruction_pointer(),
+            program.read_relative_base()
+        ),
+        Command::Help => println!("{}", HELP),
+        Command::Quit => {}
+    }
+}
+
+/// Executes one instruction, returning whether execution can continue
+fn step<I, O>(program: &mut Program<I, O>) -> bool
+where
+    I: IntcodeInput,
+    O: IntcodeOutput,
+{
+    let stopped = program.step();
+    report(program, stopped)
+}
+
+/// Describes why execution stopped, if it did, returning whether it can continue
+fn report<I, O>(program: &Program<I, O>, stopped: Result<Option<Stopped>, Error>) -> bool
+where
+    I: IntcodeInput,
+    O: IntcodeOutput,
+{
+    match stopped {
+        Ok(None) => true,
+        Ok(Some(Stopped::NeedsInput)) => {
+            println!("Waiting for input; queue some with 'input <value>...'");
+            false
+        }
+        Ok(Some(Stopped::Halted)) => {
+            println!("Program halted");
+            false
+        }
+        Ok(Some(Stopped::Watchpoint(address))) => {
+            println!("Watchpoint: [{}] = {}", address, program.memory[address]);
+            false
+        }
+        Ok(Some(Stopped::Breakpoint(address))) => {
+            println!("Breakpoint at {:04}", address);
+            false
+        }
+        Err(e) => {
+            println!("{}", e);
+            false
+        }
+    }
+}
+
+fn marker<I, O>(program: &Program<I, O>, address: usize) -> &'static str
+where
+    I: IntcodeInput,
+    O: IntcodeOutput,
+{
+    let breakpoint = program.breakpoints().any(|b| *b == address);
+    match (address == program.read_instruction_pointer(), breakpoint) {
+        (true, true) => "*>",
+        (true, false) => "=>",
+        (false, true) => "* ",
+        (false, false) => "  ",
+    }
+}
+
+fn print_current<I, O>(program: &Program<I, O>)
+where
+    I: IntcodeInput,
+    O: IntcodeOutput,
+{
+    let address = program.read_instruction_pointer();
+    if address < program.memory.len() {
+        let (line, _) =
+            disassembler::disassemble_at_with(&program.memory, address, program.instruction_set());
+        println!("{} {}", marker(program, address), line);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_commands_with_defaults() {
+        assert_eq!(Ok(Command::Step(1)), Command::parse("s"));
+        assert_eq!(Ok(Command::Step(10)), Command::parse("step 10"));
+        assert_eq!(Ok(Command::ReverseStep(1)), Command::parse("rs"));
+        assert_eq!(Ok(Command::Writer(12)), Command::parse("writer 12"));
+        assert_eq!(Ok(Command::List(None, 8)), Command::parse("list"));
+        assert_eq!(Ok(Command::List(Some(4), 2)), Command::parse("l 4 2"));
+        assert_eq!(Ok(Command::Mem(100, 16)), Command::parse("mem 100"));
+        assert_eq!(Ok(Command::Input(vec![1, -2])), Command::parse("i 1 -2"));
+    }
+
+    #[test]
+    fn rejects_missing_or_invalid_arguments() {
+        assert!(Command::parse("break").is_err());
+        assert!(Command::parse("wr").is_err());
+        assert!(Command::parse("watch x").is_err());
+        assert!(Command::parse("input 1.5").is_err());
+        assert!(Command::parse("frobnicate").is_err());
+    }
+}
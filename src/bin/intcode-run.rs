@@ -0,0 +1,294 @@
+use aoc2019::intcode::{
+    ascii::{Ascii, AsciiInput, AsciiOutput},
+    io::{IntcodeInput, IntcodeOutput, ReaderInput, WriterOutput},
+    load,
+    program::{Program, Stopped},
+};
+use std::cell::RefCell;
+use std::fs;
+use std::io::{self, BufRead, Cursor, Read, Write};
+use std::{env, process};
+
+const USAGE: &str = "\
+Usage: intcode-run [options] <program-file>
+
+Runs an Intcode program with its input and output on the terminal.
+
+Options:
+  -a, --ascii             read commands and print text instead of numbers
+  -s, --script <file>     read input from file before the terminal";
+
+/// How input and output values are shown
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Mode {
+    /// One value per line, read separated by commas or whitespace
+    Numbers,
+    /// Lines of text, with values outside the ASCII range printed as numbers
+    Ascii,
+}
+
+#[derive(Debug, PartialEq)]
+struct Options {
+    mode: Mode,
+    script: Option<String>,
+    path: String,
+}
+
+impl Options {
+    fn parse(args: &[String]) -> Result<Self, String> {
+        let mut mode = Mode::Numbers;
+        let mut script = None;
+        let mut path = None;
+
+        let mut args = args.iter();
+        while let Some(arg) = args.next() {
+            match arg.as_str() {
+                "-a" | "--ascii" => mode = Mode::Ascii,
+                "-s" | "--script" => match args.next() {
+                    Some(file) => script = Some(file.clone()),
+                    None => return Err(format!("'{}' needs a file", arg)),
+                },
+                _ if arg.starts_with('-') => return Err(format!("Unknown option '{}'", arg)),
+                _ if path.is_none() => path = Some(arg.clone()),
+                _ => return Err(format!("Unexpected argument '{}'", arg)),
+            }
+        }
+
+        match path {
+            Some(path) => Ok(Options { mode, script, path }),
+            None => Err("Missing program file".to_string()),
+        }
+    }
+}
+
+fn main() {
+    let args: Vec<String> = env::args().skip(1).collect();
+    let options = match Options::parse(&args) {
+        Ok(options) => options,
+        Err(message) => {
+            eprintln!("{}\n\n{}", message, USAGE);
+            process::exit(2);
+        }
+    };
+
+    let memory = match load::from_file(&options.path) {
+        Ok(memory) => memory,
+        Err(load::Error::Io(e)) => {
+            eprintln!("Failed to read '{}': {}", options.path, e);
+            process::exit(1);
+        }
+        Err(e) => {
+            eprintln!("Failed to parse '{}': {}", options.path, e);
+            process::exit(1);
+        }
+    };
+
+    // Scripted input is replayed first, then the terminal takes over
+    let stdin = io::stdin().lock();
+    let reader: Box<dyn BufRead> = match &options.script {
+        Some(script) => match fs::read_to_string(script) {
+            Ok(script) => Box::new(script_then(script, stdin)),
+            Err(e) => {
+                eprintln!("Failed to open script '{}': {}", script, e);
+                process::exit(1);
+            }
+        },
+        None => Box::new(stdin),
+    };
+
+    let status = match options.mode {
+        Mode::Numbers => {
+            let input = ReaderInput::new(reader);
+            let output = WriterOutput::new(io::stdout());
+            let (status, input, output) =
+                run(&memory, input, output, |output| output.error().is_some());
+            if let Some(e) = input.error() {
+                eprintln!("Failed to read input: {}", e);
+                process::exit(1);
+            }
+            if let Some(e) = output.error() {
+                eprintln!("Failed to write output: {}", e);
+                process::exit(1);
+            }
+            status
+        }
+        Mode::Ascii => {
+            let input = AsciiInput::from_reader(reader);
+            let mut stdout = io::stdout();
+            let write_error = RefCell::new(None);
+            let output = AsciiOutput::new(|ascii| {
+                let mut write_error = write_error.borrow_mut();
+                if write_error.is_none() {
+                    *write_error = write_ascii(&mut stdout, ascii).err();
+                }
+            });
+            let (status, input, mut output) =
+                run(&memory, input, output, |_| write_error.borrow().is_some());
+            output.flush();
+            drop(output);
+            if let Some(e) = input.error() {
+                eprintln!("Failed to read input: {}", e);
+                process::exit(1);
+            }
+            if let Some(e) = write_error.into_inner() {
+                eprintln!("Failed to write output: {}", e);
+                process::exit(1);
+            }
+            status
+        }
+    };
+
+    process::exit(status);
+}
+
+/// Writes a piece of ASCII output on its own line
+fn write_ascii<W: Write>(writer: &mut W, ascii: Ascii) -> io::Result<()> {
+    match ascii {
+        Ascii::Line(line) => writeln!(writer, "{}", line),
+        Ascii::Value(value) => writeln!(writer, "{}", value),
+    }
+}
+
+/// Replays the script before reading from `rest`. The script's last line is
+/// ended if it isn't already, so it can't run into the first line of `rest`.
+fn script_then<R: BufRead>(mut script: String, rest: R) -> impl BufRead {
+    if !script.is_empty() && !script.ends_with('\n') {
+        script.push('\n');
+    }
+    Cursor::new(script).chain(rest)
+}
+
+/// Runs the program to completion, or until `failed` says its output can no
+/// longer be written, returning the exit status along with its input and output
+fn run<I, O, F>(memory: &[i64], input: I, output: O, failed: F) -> (i32, I, O)
+where
+    I: IntcodeInput,
+    O: IntcodeOutput,
+    F: Fn(&O) -> bool,
+{
+    let mut program = Program::with_channels(memory, input, output);
+    let status = loop {
+        match program.step() {
+            Ok(None) if failed(program.output()) => break 1,
+            Ok(None) => {}
+            Ok(Some(Stopped::Halted)) => break 0,
+            Ok(Some(Stopped::NeedsInput)) => {
+                eprintln!("Input ended while the program was waiting for more");
+                break 3;
+            }
+            Ok(Some(stopped)) => {
+                eprintln!("Program stopped unexpectedly: {:?}", stopped);
+                break 1;
+            }
+            Err(e) => {
+                eprintln!("{}", e);
+                break 1;
+            }
+        }
+    };
+
+    let (input, output) = program.into_channels();
+    (status, input, output)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use aoc2019::intcode::assembler::assemble;
+
+    const ECHO: &str = "
+        loop:   in [value]
+                out [value]
+                jt #1, #loop
+        value:  data 0
+    ";
+
+    fn parse(args: &[&str]) -> Result<Options, String> {
+        let args: Vec<String> = args.iter().map(|arg| arg.to_string()).collect();
+        Options::parse(&args)
+    }
+
+    #[test]
+    fn parses_options() {
+        assert_eq!(
+            Ok(Options {
+                mode: Mode::Numbers,
+                script: None,
+                path: "day05.txt".to_string()
+            }),
+            parse(&["day05.txt"])
+        );
+        assert_eq!(
+            Ok(Options {
+                mode: Mode::Ascii,
+                script: Some("moves.txt".to_string()),
+                path: "day25.txt".to_string()
+            }),
+            parse(&["--ascii", "day25.txt", "-s", "moves.txt"])
+        );
+    }
+
+    #[test]
+    fn rejects_invalid_arguments() {
+        assert!(parse(&[]).is_err());
+        assert!(parse(&["day05.txt", "--script"]).is_err());
+        assert!(parse(&["--verbose", "day05.txt"]).is_err());
+        assert!(parse(&["day05.txt", "day09.txt"]).is_err());
+    }
+
+    #[test]
+    fn exits_with_zero_when_the_program_halts() {
+        let memory = assemble("in [9]\nout [9]\nhlt").unwrap();
+        let input = ReaderInput::new(&b"42\n"[..]);
+        let (status, _, output) = run(&memory, input, WriterOutput::new(Vec::new()), |_| false);
+        assert_eq!(0, status);
+        assert_eq!(b"42\n", &output.into_inner()[..]);
+    }
+
+    #[test]
+    fn exits_with_three_when_input_runs_out() {
+        let memory = assemble(ECHO).unwrap();
+        let input = ReaderInput::new(&b"1, 2\n"[..]);
+        let (status, _, output) = run(&memory, input, WriterOutput::new(Vec::new()), |_| false);
+        assert_eq!(3, status);
+        assert_eq!(b"1\n2\n", &output.into_inner()[..]);
+    }
+
+    #[test]
+    fn exits_with_one_on_a_runtime_error() {
+        let input = ReaderInput::new(&b""[..]);
+        let (status, _, _) = run(&[5555], input, WriterOutput::new(Vec::new()), |_| false);
+        assert_eq!(1, status);
+    }
+
+    #[test]
+    fn stops_when_output_fails() {
+        let memory = assemble("loop: out #1\njt #1, #loop").unwrap();
+        let input = ReaderInput::new(&b""[..]);
+        let output = WriterOutput::new(&mut [][..]);
+        let (status, _, output) = run(&memory, input, output, |output| output.error().is_some());
+        assert_eq!(1, status);
+        assert!(output.error().is_some());
+    }
+
+    #[test]
+    fn replays_the_script_before_the_terminal() {
+        let memory = assemble(ECHO).unwrap();
+        let input = ReaderInput::new(script_then("1".to_string(), &b"2\n"[..]));
+        let (_, _, output) = run(&memory, input, WriterOutput::new(Vec::new()), |_| false);
+        assert_eq!(b"1\n2\n", &output.into_inner()[..]);
+
+        let mut lines = Vec::new();
+        let input = AsciiInput::from_reader(script_then("north".to_string(), &b"south\n"[..]));
+        let output = AsciiOutput::new(|ascii| lines.push(ascii));
+        let (status, _, _) = run(&memory, input, output, |_| false);
+        assert_eq!(3, status);
+        assert_eq!(
+            vec![
+                Ascii::Line("north".to_string()),
+                Ascii::Line("south".to_string())
+            ],
+            lines
+        );
+    }
+}
@@ -1,6 +1,6 @@
 #[cfg(test)]
 mod tests {
-    use crate::intcode::program::Program;
+    use crate::intcode::{load, program::Program, search};
 
     #[test]
     fn runs_first_example_program() {
@@ -52,16 +52,11 @@ mod tests {
 
     #[test]
     fn solve_day2_part1() {
-        let input = std::fs::read_to_string("src/day02/input.txt").unwrap();
-        let mut memory: Vec<i64> = input
-            .split(',')
-            .map(|i| i.parse::<i64>().unwrap())
-            .collect();
+        let memory = load::from_file("src/day02/input.txt").unwrap();
 
-        memory[1] = 12;
-        memory[2] = 2;
-
-        let mut program = new_program!(&memory);
+        let mut program = Program::new(&memory)
+            .with_patches(&[(1, 12), (2, 2)])
+            .unwrap();
         let result = program.run();
         assert!(result.is_ok());
         assert_eq!(8017076, program.memory[0]);
@@ -69,37 +64,12 @@ mod tests {
 
     #[test]
     fn solve_day2_part2() {
-        let input = std::fs::read_to_string("src/day02/input.txt").unwrap();
-        let init: Vec<i64> = input
-            .split(',')
-            .map(|i| i.parse::<i64>().unwrap())
-            .collect();
-
-        // for i in 0..99 {
-        //     for j in 0..99 {
-        //         let mut memory = init.clone();
-        //         memory[1] = i;
-        //         memory[2] = j;
-
-        //         let mut program = new_program!(memory);
-        //         let result = program.run();
-        //         assert!(result.is_ok());
+        let init = load::from_file("src/day02/input.txt").unwrap();
 
-        //         if program.memory[0] == 19690720 {
-        //             // to see this output: `cargo test -- --nocapture`
-        //             println!("### VALUES: [1] = {}, [2] = {}", i, j);
-        //             break;
-        //         }
-        //     }
-        // }
+        let solutions = search::solve(&init, &[(1, 0..=99), (2, 0..=99)], 0, 19690720).unwrap();
+        assert_eq!(vec![vec![31, 46]], solutions);
 
-        let mut memory = init.clone();
-        memory[1] = 31;
-        memory[2] = 46;
-
-        let mut program = new_program!(&memory);
-        let result = program.run();
-        assert!(result.is_ok());
-        assert_eq!(19690720, program.memory[0]);
+        let (noun, verb) = (solutions[0][0], solutions[0][1]);
+        assert_eq!(3146, 100 * noun + verb);
     }
 }
@@ -1,15 +1,11 @@
 #[cfg(test)]
 mod tests {
-    use crate::intcode::program::Program;
+    use crate::intcode::{load, program::Program};
 
     #[test]
     fn solve_day5_part1() {
         let mut output = Vec::<i64>::new();
-        let input = std::fs::read_to_string("src/day05/input.txt").unwrap();
-        let memory: Vec<i64> = input
-            .split(',')
-            .map(|i| i.parse::<i64>().unwrap())
-            .collect();
+        let memory = load::from_file("src/day05/input.txt").unwrap();
 
         let mut program = Program::with_io(&memory, || 1, |i| output.push(i));
         assert!(program.run().is_ok());
@@ -70,11 +66,7 @@ mod tests {
     #[test]
     fn solve_day5_part2() {
         let mut output = Vec::<i64>::new();
-        let input = std::fs::read_to_string("src/day05/input.txt").unwrap();
-        let memory: Vec<i64> = input
-            .split(',')
-            .map(|i| i.parse::<i64>().unwrap())
-            .collect();
+        let memory = load::from_file("src/day05/input.txt").unwrap();
 
         let mut program = Program::with_io(&memory, || 5, |i| output.push(i));
         assert!(program.run().is_ok());
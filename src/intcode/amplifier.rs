@@ -0,0 +1,257 @@
+use super::program::{self, Program, RunState};
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum Wiring {
+    /// Each amplifier's output feeds the next; the last one's output is the result
+    Chain,
+    /// Like `Chain`, but the last amplifier's output feeds back into the first
+    /// until the amplifiers halt
+    FeedbackLoop,
+}
+
+#[derive(Clone, Debug, Eq, Fail, PartialEq)]
+pub enum Error {
+    #[fail(display = "Amplifier {} failed: {}", _0, _1)]
+    Program(usize, #[cause] program::Error),
+    #[fail(display = "Amplifier {} stopped without producing a signal", _0)]
+    NoSignal(usize),
+    #[fail(display = "No phase settings were given")]
+    NoAmplifiers,
+}
+
+/// Runs one copy of `memory` per phase setting, wired together as given, and
+/// returns the final signal. The first amplifier receives a signal of 0. Each
+/// amplifier fails with `StepLimitExceeded` rather than execute more than
+/// `step_limit` instructions; without a limit, a program that never halts
+/// never returns.
+pub fn run(
+    memory: &[i64],
+    phases: &[i64],
+    wiring: Wiring,
+    step_limit: Option<u64>,
+) -> Result<i64, Error> {
+    if phases.is_empty() {
+        return Err(Error::NoAmplifiers);
+    }
+
+    let mut amplifiers: Vec<_> = phases
+        .iter()
+        .map(|phase| {
+            let mut amplifier = Program::new(memory);
+            amplifier.set_step_limit(step_limit);
+            amplifier.push_input(*phase);
+            amplifier
+        })
+        .collect();
+
+    let mut signal = 0;
+    loop {
+        for (index, amplifier) in amplifiers.iter_mut().enumerate() {
+            amplifier.push_input(signal);
+            match amplifier
+                .run_until_blocked()
+                .map_err(|e| Error::Program(index, e))?
+            {
+                RunState::Output(value) => signal = value,
+                RunState::Halted if index == 0 && wiring == Wiring::FeedbackLoop => {
+                    return Ok(signal)
+                }
+                _ => return Err(Error::NoSignal(index)),
+            }
+        }
+
+        if wiring == Wiring::Chain {
+            return Ok(signal);
+        }
+    }
+}
+
+/// Tries every ordering of `phases` and returns the highest signal along with
+/// the ordering that produced it. `step_limit` applies to each run as for `run`.
+pub fn max_thruster_signal(
+    memory: &[i64],
+    phases: &[i64],
+    wiring: Wiring,
+    step_limit: Option<u64>,
+) -> Result<(i64, Vec<i64>), Error> {
+    let mut best: Option<(i64, Vec<i64>)> = None;
+    for ordering in permutations(phases) {
+        let signal = run(memory, &ordering, wiring, step_limit)?;
+        if best.as_ref().is_none_or(|(max, _)| signal > *max) {
+            best = Some((signal, ordering));
+        }
+    }
+
+    // Even an empty list has one ordering, for which `run` fails
+    Ok(best.expect("there is always at least one ordering"))
+}
+
+fn permutations(items: &[i64]) -> Vec<Vec<i64>> {
+    if items.len() <= 1 {
+        return vec![items.to_vec()];
+    }
+
+    let mut result = Vec::new();
+    for (index, item) in items.iter().enumerate() {
+        let mut rest = items.to_vec();
+        rest.remove(index);
+        for mut tail in permutations(&rest) {
+            tail.insert(0, *item);
+            result.push(tail);
+        }
+    }
+
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const CHAIN_EXAMPLE1: [i64; 17] = [
+        3, 15, 3, 16, 1002, 16, 10, 16, 1, 16, 15, 15, 4, 15, 99, 0, 0,
+    ];
+    const CHAIN_EXAMPLE2: [i64; 25] = [
+        3, 23, 3, 24, 1002, 24, 10, 24, 1002, 23, -1, 23, 101, 5, 23, 23, 1, 24, 23, 23, 4, 23, 99,
+        0, 0,
+    ];
+    const CHAIN_EXAMPLE3: [i64; 34] = [
+        3, 31, 3, 32, 1002, 32, 10, 32, 1001, 31, -2, 31, 1007, 31, 0, 33, 1002, 33, 7, 33, 1, 33,
+        31, 31, 1, 32, 31, 31, 4, 31, 99, 0, 0, 0,
+    ];
+    const FEEDBACK_EXAMPLE1: [i64; 29] = [
+        3, 26, 1001, 26, -4, 26, 3, 27, 1002, 27, 2, 27, 1, 27, 26, 27, 4, 27, 1001, 28, -1, 28,
+        1005, 28, 6, 99, 0, 0, 5,
+    ];
+    const FEEDBACK_EXAMPLE2: [i64; 57] = [
+        3, 52, 1001, 52, -5, 52, 3, 53, 1, 52, 56, 54, 1007, 54, 5, 55, 1005, 55, 26, 1001, 54, -5,
+        54, 1105, 1, 12, 1, 53, 54, 53, 1008, 54, 0, 55, 1001, 55, 1, 55, 2, 53, 55, 53, 4, 53,
+        1001, 56, -1, 56, 1005, 56, 6, 99, 0, 0, 0, 0, 10,
+    ];
+
+    #[test]
+    fn runs_a_chain_of_amplifiers() {
+        assert_eq!(
+            Ok(43210),
+            run(&CHAIN_EXAMPLE1, &[4, 3, 2, 1, 0], Wiring::Chain, None)
+        );
+        assert_eq!(
+            Ok(54321),
+            run(&CHAIN_EXAMPLE2, &[0, 1, 2, 3, 4], Wiring::Chain, None)
+        );
+        assert_eq!(
+            Ok(65210),
+            run(&CHAIN_EXAMPLE3, &[1, 0, 4, 3, 2], Wiring::Chain, None)
+        );
+    }
+
+    #[test]
+    fn runs_a_feedback_loop_of_amplifiers() {
+        assert_eq!(
+            Ok(139629729),
+            run(
+                &FEEDBACK_EXAMPLE1,
+                &[9, 8, 7, 6, 5],
+                Wiring::FeedbackLoop,
+                None
+            )
+        );
+        assert_eq!(
+            Ok(18216),
+            run(
+                &FEEDBACK_EXAMPLE2,
+                &[9, 7, 8, 5, 6],
+                Wiring::FeedbackLoop,
+                None
+            )
+        );
+    }
+
+    #[test]
+    fn finds_max_thruster_signal_for_a_chain() {
+        assert_eq!(
+            Ok((43210, vec![4, 3, 2, 1, 0])),
+            max_thruster_signal(&CHAIN_EXAMPLE1, &[0, 1, 2, 3, 4], Wiring::Chain, None)
+        );
+        assert_eq!(
+            Ok((65210, vec![1, 0, 4, 3, 2])),
+            max_thruster_signal(&CHAIN_EXAMPLE3, &[0, 1, 2, 3, 4], Wiring::Chain, None)
+        );
+    }
+
+    #[test]
+    fn finds_max_thruster_signal_for_a_feedback_loop() {
+        assert_eq!(
+            Ok((18216, vec![9, 7, 8, 5, 6])),
+            max_thruster_signal(
+                &FEEDBACK_EXAMPLE2,
+                &[5, 6, 7, 8, 9],
+                Wiring::FeedbackLoop,
+                None
+            )
+        );
+    }
+
+    #[test]
+    fn fails_when_an_amplifier_halts_without_a_signal() {
+        assert_eq!(
+            Err(Error::NoSignal(0)),
+            run(&[3, 0, 3, 0, 99], &[1, 2], Wiring::Chain, None)
+        );
+    }
+
+    #[test]
+    fn fails_without_amplifiers() {
+        for wiring in [Wiring::Chain, Wiring::FeedbackLoop] {
+            assert_eq!(
+                Err(Error::NoAmplifiers),
+                run(&FEEDBACK_EXAMPLE1, &[], wiring, None)
+            );
+            assert_eq!(
+                Err(Error::NoAmplifiers),
+                max_thruster_signal(&FEEDBACK_EXAMPLE1, &[], wiring, None)
+            );
+        }
+    }
+
+    #[test]
+    fn fails_when_an_amplifier_program_fails() {
+        match run(&[3, 0, 3, 0, 5555], &[1], Wiring::Chain, None) {
+            Err(Error::Program(0, e)) => {
+                assert_eq!(super::super::instruction::ErrorKind::InvalidOpcode, e.kind);
+                assert_eq!(Some(4), e.address);
+            }
+            result => panic!("unexpected result {:?}", result),
+        }
+    }
+
+    #[test]
+    fn fails_when_an_amplifier_exceeds_the_step_limit() {
+        // Reads both inputs, then spins forever
+        let memory = [3, 0, 3, 0, 1105, 1, 4];
+        match run(&memory, &[5, 6], Wiring::FeedbackLoop, Some(100)) {
+            Err(Error::Program(0, e)) => {
+                assert_eq!(
+                    super::super::instruction::ErrorKind::StepLimitExceeded,
+                    e.kind
+                );
+            }
+            result => panic!("unexpected result {:?}", result),
+        }
+    }
+
+    #[test]
+    fn generates_all_permutations() {
+        assert_eq!(
+            vec![
+                vec![1, 2, 3],
+                vec![1, 3, 2],
+                vec![2, 1, 3],
+                vec![2, 3, 1],
+                vec![3, 1, 2],
+                vec![3, 2, 1],
+            ],
+            permutations(&[1, 2, 3])
+        );
+    }
+}
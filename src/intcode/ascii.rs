@@ -0,0 +1,256 @@
+use super::io::{IntcodeInput, IntcodeOutput};
+use std::collections::VecDeque;
+use std::io::{self, BufRead};
+
+/// A piece of decoded ASCII output
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum Ascii {
+    /// A line of text, without its newline
+    Line(String),
+    /// A value outside the ASCII range, usually a numeric answer
+    Value(i64),
+}
+
+#[derive(Clone, Copy, Debug, Eq, Fail, PartialEq)]
+pub enum Error {
+    #[fail(display = "Command contains non-ASCII character '{}'", _0)]
+    NonAscii(char),
+}
+
+/// The input codes for a command, which ends with a newline whether or not
+/// `command` does. Fails if the command isn't ASCII.
+pub fn encode(command: &str) -> Result<Vec<i64>, Error> {
+    command
+        .trim_end_matches(['\r', '\n'])
+        .chars()
+        .chain(Some('\n'))
+        .map(|c| match c {
+            '\0'..='\x7f' => Ok(c as i64),
+            _ => Err(Error::NonAscii(c)),
+        })
+        .collect()
+}
+
+/// Decodes a complete output stream. Text after the last newline becomes a
+/// final line.
+pub fn decode(values: &[i64]) -> Vec<Ascii> {
+    let mut decoded = Vec::new();
+    let mut output = AsciiOutput::new(|ascii| decoded.push(ascii));
+    values.iter().for_each(|value| output.write_output(*value));
+    output.flush();
+    decoded
+}
+
+/// Feeds commands to a program a character at a time. Queued commands come
+/// first; once they run out, each line read from the reader is another
+/// command. Input ends at end of file or at the first read error or non-ASCII
+/// line, which is kept for inspection.
+pub struct AsciiInput<R: BufRead = io::Empty> {
+    pending: VecDeque<i64>,
+    reader: R,
+    error: Option<io::Error>,
+}
+
+impl AsciiInput {
+    pub fn new() -> Self {
+        AsciiInput::from_reader(io::empty())
+    }
+}
+
+impl Default for AsciiInput {
+    fn default() -> Self {
+        AsciiInput::new()
+    }
+}
+
+impl<R: BufRead> AsciiInput<R> {
+    pub fn from_reader(reader: R) -> Self {
+        AsciiInput {
+            pending: VecDeque::new(),
+            reader,
+            error: None,
+        }
+    }
+
+    /// Queues a command, to be read after any already queued. Nothing is
+    /// queued if the command isn't ASCII.
+    pub fn push_command(&mut self, command: &str) -> Result<(), Error> {
+        self.pending.extend(encode(command)?);
+        Ok(())
+    }
+
+    pub fn error(&self) -> Option<&io::Error> {
+        self.error.as_ref()
+    }
+}
+
+impl<R: BufRead> IntcodeInput for AsciiInput<R> {
+    fn read_input(&mut self) -> Option<i64> {
+        if self.pending.is_empty() && self.error.is_none() {
+            let mut line = String::new();
+            match self.reader.read_line(&mut line) {
+                Ok(0) => {}
+                Ok(_) => {
+                    if let Err(e) = self.push_command(&line) {
+                        self.error =
+                            Some(io::Error::new(io::ErrorKind::InvalidData, e.to_string()));
+                    }
+                }
+                Err(e) => self.error = Some(e),
+            }
+        }
+
+        self.pending.pop_front()
+    }
+}
+
+/// Decodes output values as ASCII text, passing each complete line to a
+/// handler. A value outside the ASCII range is passed on by itself, after any
+/// partial line before it.
+pub struct AsciiOutput<F: FnMut(Ascii)> {
+    line: String,
+    handler: F,
+}
+
+impl<F: FnMut(Ascii)> AsciiOutput<F> {
+    pub fn new(handler: F) -> Self {
+        AsciiOutput {
+            line: String::new(),
+            handler,
+        }
+    }
+
+    /// Text received since the last newline, such as a prompt
+    pub fn partial_line(&self) -> &str {
+        &self.line
+    }
+
+    /// Passes on any partial line
+    pub fn flush(&mut self) {
+        if !self.line.is_empty() {
+            (self.handler)(Ascii::Line(std::mem::take(&mut self.line)));
+        }
+    }
+}
+
+impl<F: FnMut(Ascii)> IntcodeOutput for AsciiOutput<F> {
+    fn write_output(&mut self, value: i64) {
+        match value {
+            10 => (self.handler)(Ascii::Line(std::mem::take(&mut self.line))),
+            0..=127 => self.line.push(value as u8 as char),
+            _ => {
+                self.flush();
+                (self.handler)(Ascii::Value(value));
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::{
+        assembler::assemble,
+        program::{Program, Stopped},
+    };
+    use super::*;
+
+    #[test]
+    fn encodes_commands_with_a_single_newline() {
+        assert_eq!(Ok(vec![110, 111, 114, 116, 104, 10]), encode("north"));
+        assert_eq!(encode("north"), encode("north\r\n"));
+        assert_eq!(Ok(vec![10]), encode(""));
+    }
+
+    #[test]
+    fn rejects_non_ascii_commands() {
+        assert_eq!(Err(Error::NonAscii('é')), encode("café"));
+
+        let mut input = AsciiInput::from_reader("naïve\ninv\n".as_bytes());
+        input.push_command("look").unwrap();
+        assert_eq!(Err(Error::NonAscii('→')), input.push_command("go →"));
+        let codes: Vec<_> = std::iter::from_fn(|| input.read_input()).collect();
+        assert_eq!(encode("look").unwrap(), codes);
+        assert_eq!(
+            Some(io::ErrorKind::InvalidData),
+            input.error().map(|e| e.kind())
+        );
+    }
+
+    #[test]
+    fn decodes_lines_and_values() {
+        let mut values = encode("Hull damage:").unwrap();
+        values.push(19_355_436);
+        values.extend(encode("ok").unwrap());
+        values.extend(vec![62, 32]);
+        assert_eq!(
+            vec![
+                Ascii::Line("Hull damage:".to_string()),
+                Ascii::Value(19_355_436),
+                Ascii::Line("ok".to_string()),
+                Ascii::Line("> ".to_string()),
+            ],
+            decode(&values)
+        );
+    }
+
+    #[test]
+    fn negative_values_are_not_text() {
+        assert_eq!(vec![Ascii::Value(-1)], decode(&[-1]));
+    }
+
+    #[test]
+    fn keeps_a_partial_line_until_flushed() {
+        let mut lines = Vec::new();
+        let mut output = AsciiOutput::new(|ascii| lines.push(ascii));
+        encode("Command?")
+            .unwrap()
+            .into_iter()
+            .take(8)
+            .for_each(|value| output.write_output(value));
+        assert_eq!("Command?", output.partial_line());
+        output.flush();
+        assert_eq!("", output.partial_line());
+        assert_eq!(vec![Ascii::Line("Command?".to_string())], lines);
+    }
+
+    #[test]
+    fn reads_queued_commands_before_the_reader() {
+        let mut input = AsciiInput::from_reader("take coin\r\n".as_bytes());
+        input.push_command("inv").unwrap();
+        let codes: Vec<_> = std::iter::from_fn(|| input.read_input()).collect();
+        let mut expected = encode("inv").unwrap();
+        expected.extend(encode("take coin").unwrap());
+        assert_eq!(expected, codes);
+        assert!(input.error().is_none());
+    }
+
+    #[test]
+    fn drives_a_text_program() {
+        // Echoes a line, then outputs a number
+        let memory = assemble(
+            "
+        loop:   in [c]
+                out [c]
+                eq [c], #10 -> [t]
+                jf [t], #loop
+                out #1000
+                hlt
+        c:      data 0
+        t:      data 0
+            ",
+        )
+        .unwrap();
+
+        let mut input = AsciiInput::new();
+        input.push_command("look").unwrap();
+        let mut decoded = Vec::new();
+        let mut program =
+            Program::with_channels(&memory, input, AsciiOutput::new(|a| decoded.push(a)));
+        assert_eq!(Ok(Stopped::Halted), program.run());
+        drop(program);
+        assert_eq!(
+            vec![Ascii::Line("look".to_string()), Ascii::Value(1000)],
+            decoded
+        );
+    }
+}
@@ -0,0 +1,449 @@
+use super::instruction::{ParameterMode, ParameterType};
+use super::registry::InstructionSet;
+use std::collections::HashMap;
+
+#[derive(Clone, Debug, Eq, Fail, PartialEq)]
+pub enum ErrorKind {
+    #[fail(display = "Label '{}' is defined more than once", _0)]
+    DuplicateLabel(String),
+    #[fail(display = "Write operand {} cannot be in immediate mode", _0)]
+    ImmediateWrite(usize),
+    #[fail(display = "Invalid operand '{}'", _0)]
+    InvalidOperand(String),
+    #[fail(display = "Invalid label '{}'", _0)]
+    InvalidLabel(String),
+    #[fail(display = "Address marker {} doesn't match actual address {}", _0, _1)]
+    MisplacedAddress(usize, usize),
+    #[fail(display = "Expected {} operands but found {}", _0, _1)]
+    OperandCount(usize, usize),
+    #[fail(display = "Label '{}' is never defined", _0)]
+    UndefinedLabel(String),
+    #[fail(display = "Unknown mnemonic '{}'", _0)]
+    UnknownMnemonic(String),
+}
+
+#[derive(Clone, Debug, Eq, Fail, PartialEq)]
+#[fail(display = "Encountered an error on line {} while assembling", line)]
+pub struct Error {
+    #[cause]
+    pub kind: ErrorKind,
+    pub line: usize,
+}
+
+impl Error {
+    pub fn new(kind: ErrorKind, line: usize) -> Self {
+        Error { kind, line }
+    }
+}
+
+#[derive(Debug)]
+enum Value {
+    Number(i64),
+    Label(String),
+}
+
+#[derive(Debug)]
+struct Operand {
+    mode: ParameterMode,
+    value: Value,
+}
+
+enum Statement {
+    Instruction(i64, Vec<Operand>),
+    Data(Vec<Value>),
+}
+
+/// Compiles assembly source into a memory image. Each line holds an optional
+/// `label:` and an optional statement, and `;` starts a comment:
+///
+/// ```text
+/// loop:   in [value]              ; read a number
+///         eq [value], #0 -> [rb+1]
+///         jf [rb+1], #loop
+///         hlt
+/// value:  data 0
+/// ```
+///
+/// Operands are `#n` (immediate), `[n]` (position) or `[rb+n]` (relative),
+/// where `n` is a number or a label. The write operand may be separated by
+/// `->` or by a comma. A numeric label such as `0012:` asserts the address
+/// at that point, so disassembler listings can be assembled again.
+pub fn assemble(source: &str) -> Result<Vec<i64>, Error> {
+    let mut labels = HashMap::<String, i64>::new();
+    let mut statements = Vec::<(usize, Statement)>::new();
+    let mut address = 0;
+
+    for (index, line) in source.lines().enumerate() {
+        let line_number = index + 1;
+        let mut rest = line.split(';').next().unwrap().trim();
+
+        while let Some((label, remainder)) = split_label(rest) {
+            if label.chars().all(|c| c.is_ascii_digit()) {
+                let expected = label.parse::<usize>().map_err(|_| {
+                    Error::new(ErrorKind::InvalidLabel(label.to_string()), line_number)
+                })?;
+                if expected != address {
+                    return Err(Error::new(
+                        ErrorKind::MisplacedAddress(expected, address),
+                        line_number,
+                    ));
+                }
+            } else if !is_identifier(label) {
+                return Err(Error::new(
+                    ErrorKind::InvalidLabel(label.to_string()),
+                    line_number,
+                ));
+            } else if labels.insert(label.to_string(), address as i64).is_some() {
+                return Err(Error::new(
+                    ErrorKind::DuplicateLabel(label.to_string()),
+                    line_number,
+                ));
+            }
+            rest = remainder;
+        }
+
+        if rest.is_empty() {
+            continue;
+        }
+
+        let statement = parse_statement(rest).map_err(|kind| Error::new(kind, line_number))?;
+        address += match &statement {
+            Statement::Instruction(_, operands) => 1 + operands.len(),
+            Statement::Data(values) => values.len(),
+        };
+        statements.push((line_number, statement));
+    }
+
+    let mut memory = Vec::with_capacity(address);
+    for (line_number, statement) in statements {
+        let resolve = |value: &Value| match value {
+            Value::Number(n) => Ok(*n),
+            Value::Label(name) => labels
+                .get(name)
+                .copied()
+                .ok_or_else(|| Error::new(ErrorKind::UndefinedLabel(name.clone()), line_number)),
+        };
+
+        match statement {
+            Statement::Instruction(opcode, operands) => {
+                memory.push(opcode);
+                for operand in &operands {
+                    memory.push(resolve(&operand.value)?);
+                }
+            }
+            Statement::Data(values) => {
+                for value in &values {
+                    memory.push(resolve(value)?);
+                }
+            }
+        }
+    }
+
+    Ok(memory)
+}
+
+fn split_label(line: &str) -> Option<(&str, &str)> {
+    let colon = line.find(':')?;
+    let label = line[..colon].trim();
+    if label.is_empty() || label.contains(char::is_whitespace) {
+        return None;
+    }
+
+    Some((label, line[colon + 1..].trim()))
+}
+
+fn is_identifier(text: &str) -> bool {
+    let mut chars = text.chars();
+    match chars.next() {
+        Some(c) if c.is_ascii_alphabetic() || c == '_' => {
+            chars.all(|c| c.is_ascii_alphanumeric() || c == '_')
+        }
+        _ => false,
+    }
+}
+
+fn parse_statement(text: &str) -> Result<Statement, ErrorKind> {
+    let (mnemonic, rest) = match text.find(char::is_whitespace) {
+        Some(index) => (&text[..index], text[index..].trim()),
+        None => (text, ""),
+    };
+    let mnemonic = mnemonic.to_ascii_lowercase();
+
+    // Instructions with only a write operand are listed as `IN -> [5]`
+    let rest = rest.strip_prefix("->").unwrap_or(rest).trim();
+    let args: Vec<String> = if rest.is_empty() {
+        Vec::new()
+    } else {
+        rest.replace("->", ",")
+            .split(',')
+            .map(|arg| arg.trim().to_string())
+            .collect()
+    };
+
+    if mnemonic == "data" {
+        return args
+            .iter()
+            .map(|arg| parse_value(arg).ok_or_else(|| ErrorKind::InvalidOperand(arg.clone())))
+            .collect::<Result<Vec<_>, _>>()
+            .map(Statement::Data);
+    }
+
+    let instructions = InstructionSet::<i64>::shared_standard();
+    let definition = instructions
+        .definitions()
+        .find(|definition| definition.mnemonic.eq_ignore_ascii_case(&mnemonic))
+        .ok_or_else(|| ErrorKind::UnknownMnemonic(mnemonic.clone()))?;
+    let param_types = &definition.parameters;
+
+    if args.len() != param_types.len() {
+        return Err(ErrorKind::OperandCount(param_types.len(), args.len()));
+    }
+
+    let mut opcode = definition.code;
+    let mut place = 100;
+    let mut operands = Vec::new();
+    for (index, (arg, ty)) in args.iter().zip(param_types.iter()).enumerate() {
+        let operand = parse_operand(arg).ok_or_else(|| ErrorKind::InvalidOperand(arg.clone()))?;
+        let mode = match (ty, operand.mode) {
+            (ParameterType::Write, ParameterMode::Immediate) => {
+                return Err(ErrorKind::ImmediateWrite(index + 1))
+            }
+            (_, ParameterMode::Position) => 0,
+            (_, ParameterMode::Immediate) => 1,
+            (_, ParameterMode::Relative) => 2,
+        };
+        opcode += mode * place;
+        place *= 10;
+        operands.push(operand);
+    }
+
+    Ok(Statement::Instruction(opcode, operands))
+}
+
+fn parse_operand(text: &str) -> Option<Operand> {
+    if let Some(value) = text.strip_prefix('#') {
+        return Some(Operand {
+            mode: ParameterMode::Immediate,
+            value: parse_value(value)?,
+        });
+    }
+
+    let inner = text.strip_prefix('[')?.strip_suffix(']')?.trim();
+    let offset = match inner.strip_prefix("rb") {
+        Some(offset) if !offset.starts_with(|c: char| c.is_ascii_alphanumeric() || c == '_') => {
+            offset.trim()
+        }
+        _ => {
+            return Some(Operand {
+                mode: ParameterMode::Position,
+                value: parse_value(inner)?,
+            })
+        }
+    };
+
+    let value = if offset.is_empty() {
+        Value::Number(0)
+    } else if let Some(positive) = offset.strip_prefix('+') {
+        parse_value(positive)?
+    } else if let Some(negative) = offset.strip_prefix('-') {
+        Value::Number(-negative.trim().parse::<i64>().ok()?)
+    } else {
+        return None;
+    };
+
+    Some(Operand {
+        mode: ParameterMode::Relative,
+        value,
+    })
+}
+
+fn parse_value(text: &str) -> Option<Value> {
+    let text = text.trim();
+    if let Ok(number) = text.parse::<i64>() {
+        Some(Value::Number(number))
+    } else if is_identifier(text) {
+        Some(Value::Label(text.to_string()))
+    } else {
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::{disassembler, program::Program};
+    use super::*;
+
+    #[test]
+    fn assembles_an_empty_program() {
+        assert_eq!(Ok(vec![]), assemble(""));
+        assert_eq!(Ok(vec![]), assemble("; nothing here\n\n"));
+    }
+
+    #[test]
+    fn assembles_every_mnemonic() {
+        let source = "
+            add [1], #2 -> [3]
+            mul #4, [rb+5] -> [rb-6]
+            in [7]
+            out #8
+            jt [9], #10
+            jf #11, [rb]
+            lt [12], [13], [14]
+            eq #15, #16 -> [17]
+            arb #-18
+            hlt
+        ";
+        assert_eq!(
+            Ok(vec![
+                1001, 1, 2, 3, 22102, 4, 5, -6, 3, 7, 104, 8, 1005, 9, 10, 2106, 11, 0, 7, 12, 13,
+                14, 1108, 15, 16, 17, 109, -18, 99
+            ]),
+            assemble(source)
+        );
+    }
+
+    #[test]
+    fn resolves_labels_defined_before_and_after_use() {
+        let source = "
+            start:  in [value]
+                    eq [value], #8 -> [flag]
+                    jt [flag], #done
+                    jt #1, #start
+            done:   out [flag]
+                    hlt
+            value:  data 0
+            flag:   data 0
+        ";
+        assert_eq!(
+            Ok(vec![
+                3, 15, 1008, 15, 8, 16, 1005, 16, 12, 1105, 1, 0, 4, 16, 99, 0, 0
+            ]),
+            assemble(source)
+        );
+    }
+
+    #[test]
+    fn assembled_program_runs() {
+        let source = "
+                    in [value]
+                    eq [value], #8 -> [flag]
+                    out [flag]
+                    hlt
+            value:  data 0
+            flag:   data 0
+        ";
+        let memory = assemble(source).unwrap();
+        let mut output = Vec::<i64>::new();
+        let mut program = Program::with_io(&memory, || 8, |i| output.push(i));
+        assert!(program.run().is_ok());
+        assert_eq!(&[1], &output[..]);
+    }
+
+    #[test]
+    fn distinguishes_labels_starting_with_rb_from_relative_operands() {
+        assert_eq!(
+            Ok(vec![4, 2, 99, 204, -1]),
+            assemble("out [rbx]\nrbx: hlt\nout [rb - 1]")
+        );
+    }
+
+    #[test]
+    fn assembles_data_directives_with_labels() {
+        assert_eq!(
+            Ok(vec![1, -2, 3, 0, 5]),
+            assemble("table: data 1, -2, 3, table\nDATA end\nend:")
+        );
+    }
+
+    #[test]
+    fn assembles_a_disassembled_listing() {
+        let memory = [
+            3, 21, 1008, 21, 8, 20, 1005, 20, 22, 107, 8, 21, 20, 1006, 20, 31, 1106, 0, 36, 98, 0,
+            0, 1002, 21, 125, 20, 4, 20, 1105, 1, 46, 104, 999, 1105, 1, 46, 1101, 1000, 1, 20, 4,
+            20, 1105, 1, 46, 98, 99,
+        ];
+        let listing = disassembler::disassemble(&memory).join("\n");
+        assert_eq!(Ok(memory.to_vec()), assemble(&listing));
+    }
+
+    #[test]
+    fn fails_on_mismatched_address_marker() {
+        assert_eq!(
+            Err(Error::new(ErrorKind::MisplacedAddress(3, 2), 2)),
+            assemble("0000: out #1\n0003: hlt")
+        );
+    }
+
+    #[test]
+    fn fails_on_unknown_mnemonic() {
+        assert_eq!(
+            Err(Error::new(ErrorKind::UnknownMnemonic("nop".to_string()), 2)),
+            assemble("hlt\nnop")
+        );
+    }
+
+    #[test]
+    fn fails_on_wrong_operand_count() {
+        assert_eq!(
+            Err(Error::new(ErrorKind::OperandCount(3, 2), 1)),
+            assemble("add #1, #2")
+        );
+    }
+
+    #[test]
+    fn fails_on_immediate_write_operand() {
+        assert_eq!(
+            Err(Error::new(ErrorKind::ImmediateWrite(3), 1)),
+            assemble("add #1, #2 -> #3")
+        );
+    }
+
+    #[test]
+    fn fails_on_invalid_operand() {
+        assert_eq!(
+            Err(Error::new(ErrorKind::InvalidOperand("3".to_string()), 1)),
+            assemble("out 3")
+        );
+        assert_eq!(
+            Err(Error::new(
+                ErrorKind::InvalidOperand("[rb*2]".to_string()),
+                1
+            )),
+            assemble("out [rb*2]")
+        );
+    }
+
+    #[test]
+    fn fails_on_undefined_label() {
+        assert_eq!(
+            Err(Error::new(
+                ErrorKind::UndefinedLabel("nowhere".to_string()),
+                1
+            )),
+            assemble("jt #1, #nowhere")
+        );
+    }
+
+    #[test]
+    fn fails_on_duplicate_label() {
+        assert_eq!(
+            Err(Error::new(ErrorKind::DuplicateLabel("here".to_string()), 2)),
+            assemble("here: hlt\nhere: hlt")
+        );
+    }
+
+    #[test]
+    fn fails_on_invalid_label() {
+        assert_eq!(
+            Err(Error::new(ErrorKind::InvalidLabel("1st".to_string()), 1)),
+            assemble("1st: hlt")
+        );
+        assert_eq!(
+            Err(Error::new(
+                ErrorKind::InvalidLabel("99999999999999999999999".to_string()),
+                1
+            )),
+            assemble("99999999999999999999999: hlt")
+        );
+    }
+}
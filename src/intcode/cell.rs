@@ -0,0 +1,126 @@
+use super::instruction::Value;
+use num::bigint::Sign;
+use num::{BigInt, CheckedAdd, CheckedMul, One, ToPrimitive, Zero};
+use std::fmt::{Debug, Display};
+use std::hash::Hash;
+
+/// The value held by one memory cell. `i64` is the default; `i32` makes
+/// memory more compact, while `i128` and `BigInt` fit larger results.
+pub trait Cell:
+    Clone
+    + Debug
+    + Default
+    + Display
+    + Hash
+    + Ord
+    + Send
+    + Sync
+    + 'static
+    + Zero
+    + One
+    + ToPrimitive
+    + CheckedAdd
+    + CheckedMul
+{
+    /// The value to quote in an error
+    fn to_value(&self) -> Value {
+        Value::Exact(self.to_i128().expect("primitive cells fit in i128"))
+    }
+
+    fn wrapping_add(&self, other: &Self) -> Self;
+    fn wrapping_mul(&self, other: &Self) -> Self;
+    fn saturating_add(&self, other: &Self) -> Self;
+    fn saturating_mul(&self, other: &Self) -> Self;
+}
+
+macro_rules! primitive_cell {
+    ($($t:ty),*) => {
+        $(
+            impl Cell for $t {
+                fn wrapping_add(&self, other: &Self) -> Self {
+                    <$t>::wrapping_add(*self, *other)
+                }
+
+                fn wrapping_mul(&self, other: &Self) -> Self {
+                    <$t>::wrapping_mul(*self, *other)
+                }
+
+                fn saturating_add(&self, other: &Self) -> Self {
+                    <$t>::saturating_add(*self, *other)
+                }
+
+                fn saturating_mul(&self, other: &Self) -> Self {
+                    <$t>::saturating_mul(*self, *other)
+                }
+            }
+        )*
+    };
+}
+
+primitive_cell!(i32, i64, i128);
+
+/// Arbitrary precision, so arithmetic never overflows whatever the policy
+impl Cell for BigInt {
+    fn to_value(&self) -> Value {
+        self.to_i128().map_or_else(
+            || Value::Approximate {
+                negative: self.sign() == Sign::Minus,
+                bits: self.bits(),
+            },
+            Value::Exact,
+        )
+    }
+
+    fn wrapping_add(&self, other: &Self) -> Self {
+        self + other
+    }
+
+    fn wrapping_mul(&self, other: &Self) -> Self {
+        self * other
+    }
+
+    fn saturating_add(&self, other: &Self) -> Self {
+        self + other
+    }
+
+    fn saturating_mul(&self, other: &Self) -> Self {
+        self * other
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn primitive_cells_wrap_and_saturate_at_their_own_width() {
+        assert_eq!(i32::MIN, Cell::wrapping_add(&i32::MAX, &1));
+        assert_eq!(i32::MAX, Cell::saturating_mul(&i32::MAX, &2));
+        assert_eq!(
+            i128::from(i64::MAX) + 1,
+            Cell::wrapping_add(&i128::from(i64::MAX), &1)
+        );
+    }
+
+    #[test]
+    fn big_cells_never_overflow() {
+        let max = BigInt::from(i64::MAX);
+        let expected = BigInt::from(i128::from(i64::MAX) * i128::from(i64::MAX));
+        assert_eq!(expected, Cell::saturating_mul(&max, &max));
+        assert_eq!(Some(expected.clone()), max.checked_mul(&max));
+    }
+
+    #[test]
+    fn quotes_values_exactly_up_to_i128() {
+        assert_eq!(Value::Exact(-5), (-5_i32).to_value());
+        assert_eq!(Value::Exact(i128::MAX), i128::MAX.to_value());
+        assert_eq!(Value::Exact(i128::MIN), BigInt::from(i128::MIN).to_value());
+        assert_eq!(
+            Value::Approximate {
+                negative: true,
+                bits: 200
+            },
+            (-(BigInt::one() << 199)).to_value()
+        );
+    }
+}
@@ -0,0 +1,472 @@
+use super::cell::Cell;
+use super::disassembler;
+use super::instruction::{ParameterMode, ParameterType};
+use super::registry::{Flow, InstructionSet};
+use petgraph::dot::Dot;
+use petgraph::graph::{Graph, NodeIndex};
+use std::collections::{BTreeMap, BTreeSet};
+use std::convert::TryFrom;
+use std::fmt;
+
+/// A decoded instruction and the raw parameter values that follow it
+#[derive(Clone, Debug, PartialEq)]
+pub struct Instruction {
+    pub address: usize,
+    pub mnemonic: &'static str,
+    /// The type and mode of each parameter
+    pub parameters: Vec<(ParameterType, ParameterMode)>,
+    pub flow: Flow,
+    pub params: Vec<i64>,
+}
+
+impl Instruction {
+    /// The number of memory cells the instruction occupies, including the opcode
+    pub fn size(&self) -> usize {
+        1 + self.params.len()
+    }
+}
+
+impl fmt::Display for Instruction {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "{:04}: {}",
+            self.address,
+            disassembler::format_parts(self.mnemonic, &self.parameters, &self.params)
+        )
+    }
+}
+
+/// How control leaves a basic block
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum Exit {
+    /// Runs into the start of the next block
+    FallThrough,
+    /// Ends with a jump, conditional or not
+    Branch,
+    Halt,
+    /// Reaches a cell, with this value, that doesn't decode to a complete
+    /// instruction
+    Invalid(i64),
+    /// Runs off the end of memory, which halts the program
+    EndOfMemory,
+}
+
+/// A run of instructions that always execute in order, from `start` up to
+/// but not including `end`
+#[derive(Clone, Debug, PartialEq)]
+pub struct BasicBlock {
+    pub start: usize,
+    pub end: usize,
+    pub instructions: Vec<Instruction>,
+    pub exit: Exit,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum Node {
+    Block(BasicBlock),
+    /// Stands for the targets of every jump whose address is only known at
+    /// run time, or that lies outside memory
+    Unknown,
+}
+
+/// Lists a block's instructions, one per line
+impl fmt::Display for Node {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let block = match self {
+            Node::Block(block) => block,
+            Node::Unknown => return write!(f, "unknown"),
+        };
+
+        let mut lines: Vec<_> = block.instructions.iter().map(|i| i.to_string()).collect();
+        match block.exit {
+            Exit::Invalid(value) => lines.push(format!("{:04}: DATA {}", block.end, value)),
+            Exit::EndOfMemory => lines.push(format!("{:04}: END", block.end)),
+            _ => {}
+        }
+        write!(f, "{}", lines.join("\n"))
+    }
+}
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum Edge {
+    FallThrough,
+    /// A jump's target
+    Taken,
+    /// The instruction after a conditional jump
+    NotTaken,
+}
+
+impl fmt::Display for Edge {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let label = match self {
+            Edge::FallThrough => "",
+            Edge::Taken => "taken",
+            Edge::NotTaken => "not taken",
+        };
+        write!(f, "{}", label)
+    }
+}
+
+/// Where a jump goes
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+enum Target {
+    Address(usize),
+    Unknown,
+}
+
+/// The basic blocks reachable from address 0, as decoded statically. Code
+/// that modifies itself may take paths the graph doesn't show.
+pub struct ControlFlowGraph {
+    pub graph: Graph<Node, Edge>,
+    blocks: BTreeMap<usize, NodeIndex>,
+}
+
+impl ControlFlowGraph {
+    pub fn build(memory: &[i64]) -> Self {
+        ControlFlowGraph::build_with(memory, &InstructionSet::<i64>::shared_standard())
+    }
+
+    /// Like `build`, but decodes the instructions of `instructions`
+    pub fn build_with<C: Cell>(memory: &[i64], instructions: &InstructionSet<C>) -> Self {
+        let decode = |address| decode(memory, address, instructions);
+        let leaders = find_leaders(memory, &decode);
+
+        let mut graph = Graph::new();
+        let mut blocks = BTreeMap::new();
+        let mut exits = Vec::new();
+        for leader in &leaders {
+            let (block, successors) = decode_block(memory, *leader, &leaders, &decode);
+            blocks.insert(*leader, graph.add_node(Node::Block(block)));
+            exits.push((*leader, successors));
+        }
+
+        let mut unknown = None;
+        for (leader, successors) in exits {
+            for (target, edge) in successors {
+                let to = match target {
+                    Target::Address(address) => blocks[&address],
+                    Target::Unknown => {
+                        *unknown.get_or_insert_with(|| graph.add_node(Node::Unknown))
+                    }
+                };
+                graph.add_edge(blocks[&leader], to, edge);
+            }
+        }
+
+        ControlFlowGraph { graph, blocks }
+    }
+
+    /// The node of the block that starts at address 0
+    pub fn entry(&self) -> NodeIndex {
+        self.blocks[&0]
+    }
+
+    /// The node of the block that starts at `address`, if there is one
+    pub fn block_at(&self, address: usize) -> Option<NodeIndex> {
+        self.blocks.get(&address).copied()
+    }
+
+    /// Renders the graph in Graphviz DOT format
+    pub fn to_dot(&self) -> String {
+        format!("{:#}", Dot::new(&self.graph))
+    }
+}
+
+// Finds the start of every block reachable from address 0: the entry point,
+// the target of every resolved jump, and the instruction after every jump
+// that might not be taken
+fn find_leaders<D>(memory: &[i64], decode: &D) -> BTreeSet<usize>
+where
+    D: Fn(usize) -> Option<Instruction>,
+{
+    let mut leaders = BTreeSet::new();
+    let mut decoded = BTreeSet::new();
+    let mut pending = vec![0];
+    leaders.insert(0);
+
+    while let Some(mut address) = pending.pop() {
+        while let Some(instruction) = decode(address) {
+            if !decoded.insert(address) {
+                break;
+            }
+
+            match instruction.flow {
+                Flow::Next | Flow::Input => address += instruction.size(),
+                Flow::Halt => break,
+                Flow::JumpIf(_) | Flow::Unknown => {
+                    for (target, _) in branch_targets(memory, &instruction) {
+                        if let Target::Address(target) = target {
+                            if leaders.insert(target) {
+                                pending.push(target);
+                            }
+                        }
+                    }
+                    break;
+                }
+            }
+        }
+    }
+
+    leaders
+}
+
+fn decode_block<D>(
+    memory: &[i64],
+    start: usize,
+    leaders: &BTreeSet<usize>,
+    decode: &D,
+) -> (BasicBlock, Vec<(Target, Edge)>)
+where
+    D: Fn(usize) -> Option<Instruction>,
+{
+    let mut instructions = Vec::new();
+    let mut address = start;
+
+    let (exit, successors) = loop {
+        if address == memory.len() {
+            break (Exit::EndOfMemory, vec![]);
+        }
+        if address != start && leaders.contains(&address) {
+            break (
+                Exit::FallThrough,
+                vec![(Target::Address(address), Edge::FallThrough)],
+            );
+        }
+
+        let instruction = match decode(address) {
+            Some(instruction) => instruction,
+            None => break (Exit::Invalid(memory[address]), vec![]),
+        };
+        address += instruction.size();
+        let flow = instruction.flow;
+        instructions.push(instruction);
+
+        match flow {
+            Flow::Next | Flow::Input => {}
+            Flow::Halt => break (Exit::Halt, vec![]),
+            Flow::JumpIf(_) | Flow::Unknown => {
+                let targets = branch_targets(memory, instructions.last().unwrap());
+                break (Exit::Branch, targets);
+            }
+        }
+    };
+
+    let block = BasicBlock {
+        start,
+        end: address,
+        instructions,
+        exit,
+    };
+    (block, successors)
+}
+
+fn decode<C: Cell>(
+    memory: &[i64],
+    address: usize,
+    instructions: &InstructionSet<C>,
+) -> Option<Instruction> {
+    let decoded = instructions.decode(*memory.get(address)?).ok()?;
+    let params = memory.get(address + 1..address + decoded.size())?.to_vec();
+    Some(Instruction {
+        address,
+        mnemonic: decoded.definition.mnemonic,
+        parameters: decoded.parameters(),
+        flow: decoded.definition.flow,
+        params,
+    })
+}
+
+// Where a jump can go. A condition in immediate mode is decided statically, so
+// only one way is possible. An instruction that may go anywhere leads only to
+// the unknown node.
+fn branch_targets(memory: &[i64], instruction: &Instruction) -> Vec<(Target, Edge)> {
+    let anywhere = vec![(Target::Unknown, Edge::Taken)];
+    let cmp = match instruction.flow {
+        Flow::JumpIf(cmp) => cmp,
+        Flow::Unknown => return anywhere,
+        _ => return vec![],
+    };
+    let (condition_mode, target_mode) = match instruction.parameters[..] {
+        [(_, condition_mode), (_, target_mode), ..] => (condition_mode, target_mode),
+        _ => return anywhere,
+    };
+
+    let target = match target_mode {
+        ParameterMode::Immediate => usize::try_from(instruction.params[1])
+            .ok()
+            .filter(|address| *address <= memory.len())
+            .map_or(Target::Unknown, Target::Address),
+        _ => Target::Unknown,
+    };
+    let next = Target::Address(instruction.address + instruction.size());
+
+    match condition_mode {
+        ParameterMode::Immediate if (instruction.params[0] != 0) == cmp => {
+            vec![(target, Edge::Taken)]
+        }
+        ParameterMode::Immediate => vec![(next, Edge::NotTaken)],
+        _ => vec![(target, Edge::Taken), (next, Edge::NotTaken)],
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::assembler::assemble;
+    use super::super::registry::Definition;
+    use super::*;
+    use petgraph::visit::EdgeRef;
+
+    fn block(cfg: &ControlFlowGraph, address: usize) -> &BasicBlock {
+        match &cfg.graph[cfg.block_at(address).unwrap()] {
+            Node::Block(block) => block,
+            Node::Unknown => panic!("no block at {}", address),
+        }
+    }
+
+    fn edges(cfg: &ControlFlowGraph, address: usize) -> Vec<(Option<usize>, Edge)> {
+        let mut edges: Vec<_> = cfg
+            .graph
+            .edges(cfg.block_at(address).unwrap())
+            .map(|edge| {
+                let to = match &cfg.graph[edge.target()] {
+                    Node::Block(block) => Some(block.start),
+                    Node::Unknown => None,
+                };
+                (to, *edge.weight())
+            })
+            .collect();
+        edges.sort_by_key(|(to, _)| *to);
+        edges
+    }
+
+    #[test]
+    fn straight_line_code_is_one_block() {
+        let cfg = ControlFlowGraph::build(&[1, 0, 0, 0, 99]);
+        assert_eq!(1, cfg.graph.node_count());
+        assert_eq!(0, cfg.graph.edge_count());
+        assert_eq!(Some(cfg.entry()), cfg.block_at(0));
+
+        let entry = block(&cfg, 0);
+        assert_eq!((0, 5), (entry.start, entry.end));
+        assert_eq!(2, entry.instructions.len());
+        assert_eq!(Exit::Halt, entry.exit);
+    }
+
+    #[test]
+    fn splits_blocks_at_jumps_and_their_targets() {
+        let memory = assemble(
+            "
+                in [x]
+        loop:   add [x], #-1 -> [x]
+                jt [x], #loop
+                out [x]
+                hlt
+        x:      data 0
+            ",
+        )
+        .unwrap();
+        let cfg = ControlFlowGraph::build(&memory);
+        assert_eq!(3, cfg.graph.node_count());
+
+        assert_eq!(Exit::FallThrough, block(&cfg, 0).exit);
+        assert_eq!(vec![(Some(2), Edge::FallThrough)], edges(&cfg, 0));
+
+        assert_eq!((2, 9), (block(&cfg, 2).start, block(&cfg, 2).end));
+        assert_eq!(Exit::Branch, block(&cfg, 2).exit);
+        assert_eq!(
+            vec![(Some(2), Edge::Taken), (Some(9), Edge::NotTaken)],
+            edges(&cfg, 2)
+        );
+
+        assert_eq!(Exit::Halt, block(&cfg, 9).exit);
+        assert!(edges(&cfg, 9).is_empty());
+        assert_eq!(None, cfg.block_at(12), "data is unreachable");
+    }
+
+    #[test]
+    fn decides_immediate_conditions_statically() {
+        // jt #1, #6 is always taken, so the HLT at 3 is never reached
+        let memory = [1105, 1, 6, 99, 0, 0, 1106, 1, 0, 99];
+        let cfg = ControlFlowGraph::build(&memory);
+        assert_eq!(vec![(Some(6), Edge::Taken)], edges(&cfg, 0));
+        assert_eq!(None, cfg.block_at(3));
+        // jf #1, #0 is never taken
+        assert_eq!(vec![(Some(9), Edge::NotTaken)], edges(&cfg, 6));
+    }
+
+    #[test]
+    fn marks_dynamic_jumps_as_unknown() {
+        let memory = [5, 7, 8, 1105, 1, -4, 99, 1, 3];
+        let cfg = ControlFlowGraph::build(&memory);
+        assert_eq!(
+            vec![(None, Edge::Taken), (Some(3), Edge::NotTaken)],
+            edges(&cfg, 0)
+        );
+        // A negative target is outside memory
+        assert_eq!(vec![(None, Edge::Taken)], edges(&cfg, 3));
+        assert_eq!(
+            1,
+            cfg.graph
+                .raw_nodes()
+                .iter()
+                .filter(|node| node.weight == Node::Unknown)
+                .count()
+        );
+    }
+
+    #[test]
+    fn stops_blocks_at_undecodable_cells_and_the_end_of_memory() {
+        let cfg = ControlFlowGraph::build(&[1, 0, 0, 0, 5555]);
+        assert_eq!(Exit::Invalid(5555), block(&cfg, 0).exit);
+        assert_eq!(4, block(&cfg, 0).end);
+
+        let cfg = ControlFlowGraph::build(&[1105, 1, 3]);
+        assert_eq!(vec![(Some(3), Edge::Taken)], edges(&cfg, 0));
+        assert_eq!(Exit::EndOfMemory, block(&cfg, 3).exit);
+        assert!(block(&cfg, 3).instructions.is_empty());
+    }
+
+    #[test]
+    fn follows_the_flow_of_registered_instructions() {
+        let mut instructions: InstructionSet = InstructionSet::standard();
+        let mut define = |code, mnemonic, parameters, flow| {
+            instructions
+                .register(Definition {
+                    code,
+                    mnemonic,
+                    parameters,
+                    flow,
+                    execute: |_, _| Ok(None),
+                })
+                .unwrap()
+        };
+        define(42, "GOTO", vec![ParameterType::Read], Flow::Unknown);
+        define(43, "STOP", vec![], Flow::Halt);
+
+        let memory = [1005, 7, 5, 42, 0, 43, 0, 0];
+        let cfg = ControlFlowGraph::build_with(&memory, &instructions);
+        assert_eq!(
+            vec![(Some(3), Edge::NotTaken), (Some(5), Edge::Taken)],
+            edges(&cfg, 0)
+        );
+        assert_eq!("GOTO", block(&cfg, 3).instructions[0].mnemonic);
+        assert_eq!(vec![(None, Edge::Taken)], edges(&cfg, 3));
+        assert_eq!(Exit::Halt, block(&cfg, 5).exit);
+        assert_eq!("0005: STOP", block(&cfg, 5).instructions[0].to_string());
+
+        let cfg = ControlFlowGraph::build(&memory);
+        assert_eq!(Exit::Invalid(42), block(&cfg, 3).exit);
+    }
+
+    #[test]
+    fn renders_dot() {
+        let cfg = ControlFlowGraph::build(&[1005, 6, 5, 104, 7, 99, 0]);
+        let dot = cfg.to_dot();
+        assert!(dot.starts_with("digraph {"));
+        assert!(dot.contains("label = \"0000: JT [6], #5\\l\""));
+        assert!(dot.contains("label = \"0003: OUT #7\\l\""));
+        assert!(dot.contains("label = \"0005: HLT\\l\""));
+        assert!(dot.contains("label = \"not taken\\l\""));
+    }
+}
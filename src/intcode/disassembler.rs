@@ -0,0 +1,192 @@
+use super::cell::Cell;
+use super::instruction::{ParameterMode, ParameterType};
+use super::registry::InstructionSet;
+
+/// Produces one line per instruction, e.g. `0012: ADD [15], #3 -> [20]`. Cells
+/// that don't decode to a complete instruction are listed one at a time as
+/// `DATA`.
+pub fn disassemble(memory: &[i64]) -> Vec<String> {
+    disassemble_with(memory, &InstructionSet::<i64>::shared_standard())
+}
+
+/// Like `disassemble`, but decodes the instructions of `instructions`
+pub fn disassemble_with<C: Cell>(memory: &[i64], instructions: &InstructionSet<C>) -> Vec<String> {
+    let mut lines = Vec::new();
+    let mut address = 0;
+    while address < memory.len() {
+        let (line, size) = disassemble_at_with(memory, address, instructions);
+        lines.push(line);
+        address += size;
+    }
+
+    lines
+}
+
+/// Disassembles the instruction at `address`, returning the line and the
+/// number of cells it occupies
+pub fn disassemble_at(memory: &[i64], address: usize) -> (String, usize) {
+    disassemble_at_with(memory, address, &InstructionSet::<i64>::shared_standard())
+}
+
+/// Like `disassemble_at`, but decodes the instructions of `instructions`
+pub fn disassemble_at_with<C: Cell>(
+    memory: &[i64],
+    address: usize,
+    instructions: &InstructionSet<C>,
+) -> (String, usize) {
+    let value = memory.get(address).copied().unwrap_or(0);
+    match instructions.decode(value) {
+        Ok(decoded) if address + decoded.size() <= memory.len() => {
+            let params = &memory[address + 1..address + decoded.size()];
+            let text = format_parts(decoded.definition.mnemonic, &decoded.parameters(), params);
+            (format!("{:04}: {}", address, text), decoded.size())
+        }
+        _ => (format!("{:04}: DATA {}", address, value), 1),
+    }
+}
+
+/// Formats an instruction from its mnemonic, the type and mode of each
+/// parameter, and the raw parameter values
+pub fn format_parts<C: Cell>(
+    mnemonic: &str,
+    parameters: &[(ParameterType, ParameterMode)],
+    params: &[C],
+) -> String {
+    let mut reads = Vec::new();
+    let mut writes = Vec::new();
+    for ((ty, mode), value) in parameters.iter().zip(params) {
+        match ty {
+            ParameterType::Read => reads.push(format_operand(*mode, value)),
+            ParameterType::Write => writes.push(format_operand(*mode, value)),
+        }
+    }
+
+    let mut text = mnemonic.to_string();
+    if !reads.is_empty() {
+        text.push(' ');
+        text.push_str(&reads.join(", "));
+    }
+    for write in writes {
+        text.push_str(" -> ");
+        text.push_str(&write);
+    }
+
+    text
+}
+
+fn format_operand<C: Cell>(mode: ParameterMode, value: &C) -> String {
+    match mode {
+        ParameterMode::Position => format!("[{}]", value),
+        ParameterMode::Immediate => format!("#{}", value),
+        ParameterMode::Relative if *value < C::zero() => format!("[rb{}]", value),
+        ParameterMode::Relative => format!("[rb+{}]", value),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::registry::{Definition, Flow};
+    use super::*;
+
+    #[test]
+    fn disassembles_an_empty_program() {
+        assert!(disassemble(&[]).is_empty());
+    }
+
+    #[test]
+    fn disassembles_arithmetic_instructions() {
+        assert_eq!(
+            vec![
+                "0000: ADD [15], #3 -> [20]",
+                "0004: MUL #2, [rb-1] -> [rb+7]"
+            ],
+            disassemble(&[1001, 15, 3, 20, 22102, 2, -1, 7])
+        );
+    }
+
+    #[test]
+    fn disassembles_io_and_control_flow_instructions() {
+        assert_eq!(
+            vec![
+                "0000: IN -> [9]",
+                "0002: OUT #9",
+                "0004: JT [9], #0",
+                "0007: ARB #-3",
+                "0009: HLT",
+            ],
+            disassemble(&[3, 9, 104, 9, 1005, 9, 0, 109, -3, 99])
+        );
+    }
+
+    #[test]
+    fn disassembles_comparison_instructions() {
+        assert_eq!(
+            vec![
+                "0000: LT #1, #2 -> [0]",
+                "0004: EQ [0], [1] -> [2]",
+                "0008: JF [0], #3"
+            ],
+            disassemble(&[1107, 1, 2, 0, 8, 0, 1, 2, 1006, 0, 3])
+        );
+    }
+
+    #[test]
+    fn renders_unknown_opcodes_as_data() {
+        assert_eq!(
+            vec![
+                "0000: HLT",
+                "0001: DATA 5555",
+                "0002: DATA -1",
+                "0003: OUT #7"
+            ],
+            disassemble(&[99, 5555, -1, 104, 7])
+        );
+    }
+
+    #[test]
+    fn renders_invalid_parameter_modes_as_data() {
+        assert_eq!(
+            vec!["0000: DATA 11101", "0001: DATA 1", "0002: DATA 2"],
+            disassemble(&[11101, 1, 2])
+        );
+    }
+
+    #[test]
+    fn renders_truncated_instructions_as_data() {
+        assert_eq!(
+            vec!["0000: OUT #4", "0002: DATA 1", "0003: DATA 5"],
+            disassemble(&[104, 4, 1, 5])
+        );
+    }
+
+    #[test]
+    fn disassembles_registered_instructions() {
+        let mut instructions: InstructionSet = InstructionSet::standard();
+        instructions
+            .register(Definition {
+                code: 42,
+                mnemonic: "SWAP",
+                parameters: vec![ParameterType::Write, ParameterType::Write],
+                flow: Flow::Next,
+                execute: |_, _| Ok(None),
+            })
+            .unwrap();
+        assert_eq!(
+            vec!["0000: SWAP -> [1] -> [rb+2]", "0003: HLT"],
+            disassemble_with(&[2042, 1, 2, 99], &instructions)
+        );
+        assert_eq!(
+            ("0000: DATA 2042".to_string(), 1),
+            disassemble_at(&[2042, 1, 2, 99], 0)
+        );
+    }
+
+    #[test]
+    fn disassembles_a_single_instruction() {
+        let memory = [104, 4, 1101, 1, 2, 0];
+        assert_eq!(
+            ("0002: ADD #1, #2 -> [0]".to_string(), 4),
+            disassemble_at(&memory, 2)
+        );
+    }
+}
@@ -0,0 +1,93 @@
+use super::io::{IntcodeInput, IntcodeOutput};
+use super::program::{Error, Program};
+use std::sync::Arc;
+
+/// A loaded memory image that can be instantiated repeatedly, with different
+/// patches each time, without reloading it. Clones share the same memory, so
+/// an image can be handed to many threads, and each program it creates starts
+/// from a private copy.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Image {
+    memory: Arc<[i64]>,
+}
+
+impl Image {
+    pub fn new(memory: &[i64]) -> Self {
+        Image {
+            memory: memory.into(),
+        }
+    }
+
+    pub fn memory(&self) -> &[i64] {
+        &self.memory
+    }
+
+    /// Creates a program without I/O from the image, with `patches` applied
+    pub fn program(&self, patches: &[(usize, i64)]) -> Result<Program<(), ()>, Error> {
+        Program::new(&self.memory).with_patches(patches)
+    }
+
+    /// Creates a program with the given I/O from the image, with `patches` applied
+    pub fn program_with_channels<I, O>(
+        &self,
+        patches: &[(usize, i64)],
+        input: I,
+        output: O,
+    ) -> Result<Program<I, O>, Error>
+    where
+        I: IntcodeInput,
+        O: IntcodeOutput,
+    {
+        Program::with_channels(&self.memory, input, output).with_patches(patches)
+    }
+}
+
+impl From<Vec<i64>> for Image {
+    fn from(memory: Vec<i64>) -> Self {
+        Image {
+            memory: memory.into(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::{instruction::ErrorKind, program::Stopped};
+    use super::*;
+    use std::collections::VecDeque;
+
+    #[test]
+    fn instantiates_programs_with_different_patches() {
+        let image = Image::from(vec![1, 0, 0, 0, 99]);
+
+        let mut program = image.program(&[(1, 4), (2, 4)]).unwrap();
+        assert_eq!(Ok(Stopped::Halted), program.run());
+        assert_eq!(198, program.memory[0]);
+
+        let mut program = image.program(&[]).unwrap();
+        assert_eq!(Ok(Stopped::Halted), program.run());
+        assert_eq!(2, program.memory[0]);
+
+        assert_eq!(&[1, 0, 0, 0, 99], image.memory());
+    }
+
+    #[test]
+    fn instantiates_programs_with_io() {
+        let image = Image::new(&[3, 0, 4, 0, 99, 0]);
+        let mut output = Vec::new();
+        let mut program = image
+            .program_with_channels(&[(1, 5), (3, 5)], VecDeque::from(vec![12]), &mut output)
+            .unwrap();
+        assert_eq!(Ok(Stopped::Halted), program.run());
+        assert_eq!(vec![12], output);
+    }
+
+    #[test]
+    fn rejects_patches_outside_the_image() {
+        let image = Image::new(&[99]);
+        assert_eq!(
+            Some(ErrorKind::PatchOutOfRange(1)),
+            image.program(&[(1, 0)]).err().map(|e| e.kind)
+        );
+    }
+}
@@ -1,167 +1,200 @@
-#[derive(Clone, Copy, Debug, PartialEq)]
+use super::cell::Cell;
+use std::fmt;
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
 pub enum ParameterMode {
     Position,
     Immediate,
-}
-
-#[derive(Debug, PartialEq)]
-pub enum Opcode {
-    Addition {
-        param1: ParameterMode,
-        param2: ParameterMode,
-    },
-    Equals {
-        param1: ParameterMode,
-        param2: ParameterMode,
-    },
-    Halt,
-    JumpIf {
-        cmp: bool,
-        param1: ParameterMode,
-        param2: ParameterMode,
-    },
-    LessThan {
-        param1: ParameterMode,
-        param2: ParameterMode,
-    },
-    Multiplication {
-        param1: ParameterMode,
-        param2: ParameterMode,
-    },
-    Print {
-        param: ParameterMode,
-    },
-    Store,
-}
-
-impl Opcode {
-    const FIRST: u32 = 1;
-    const SECOND: u32 = 2;
-
-    pub fn parse(value: i64) -> Result<Self, ErrorKind> {
-        match value % 100 {
-            1 => Ok(Opcode::Addition {
-                param1: Self::parse_parameter_mode(value, Self::FIRST)?,
-                param2: Self::parse_parameter_mode(value, Self::SECOND)?,
-            }),
-            2 => Ok(Opcode::Multiplication {
-                param1: Self::parse_parameter_mode(value, Self::FIRST)?,
-                param2: Self::parse_parameter_mode(value, Self::SECOND)?,
-            }),
-            3 => Ok(Opcode::Store),
-            4 => Ok(Opcode::Print {
-                param: Self::parse_parameter_mode(value, Self::FIRST)?,
-            }),
-            5 => Ok(Opcode::JumpIf {
-                cmp: true,
-                param1: Self::parse_parameter_mode(value, Self::FIRST)?,
-                param2: Self::parse_parameter_mode(value, Self::SECOND)?,
-            }),
-            6 => Ok(Opcode::JumpIf {
-                cmp: false,
-                param1: Self::parse_parameter_mode(value, Self::FIRST)?,
-                param2: Self::parse_parameter_mode(value, Self::SECOND)?,
-            }),
-            7 => Ok(Opcode::LessThan {
-                param1: Self::parse_parameter_mode(value, Self::FIRST)?,
-                param2: Self::parse_parameter_mode(value, Self::SECOND)?,
-            }),
-            8 => Ok(Opcode::Equals {
-                param1: Self::parse_parameter_mode(value, Self::FIRST)?,
-                param2: Self::parse_parameter_mode(value, Self::SECOND)?,
-            }),
-            99 => Ok(Opcode::Halt),
-            _ => Err(ErrorKind::InvalidOpcode),
-        }
-    }
-
-    fn parse_parameter_mode(value: i64, which: u32) -> Result<ParameterMode, ErrorKind> {
-        let place = 10_i64.checked_pow(which + 1).unwrap();
-        match value / place {
-            0 | 10 => Ok(ParameterMode::Position),
-            1 | 11 => Ok(ParameterMode::Immediate),
-            _ => Err(ErrorKind::InvalidParameterMode(which as usize)),
-        }
-    }
+    Relative,
 }
 
 #[derive(Clone, Copy, Debug, Eq, Fail, PartialEq)]
 pub enum ErrorKind {
-    #[fail(display = "Parameter at offset {} is out of range", _0)]
-    AddressOutOfRange(usize),
+    #[fail(display = "Arithmetic result doesn't fit in a memory cell")]
+    ArithmeticOverflow,
+    #[fail(
+        display = "Parameter refers to address {}, beyond the memory limit",
+        _0
+    )]
+    AddressOutOfRange(Value),
+    #[fail(display = "{}", _0)]
+    ExtensionFailed(&'static str),
+    #[fail(display = "No input was available")]
+    InputUnavailable,
     #[fail(display = "Encountered invalid opcode")]
     InvalidOpcode,
     #[fail(display = "Encountered invalid mode for parameter at offset {}", _0)]
     InvalidParameterMode(usize),
+    #[fail(display = "Jump to address {}, beyond the end of the program", _0)]
+    JumpOutOfRange(Value),
+    #[fail(display = "Parameter refers to negative address {}", _0)]
+    NegativeAddress(Value),
+    #[fail(display = "Jump to negative address {}", _0)]
+    NegativeJump(Value),
     #[fail(display = "Not enough parameters in memory to interpret instruction")]
     NotEnoughParameters,
     #[fail(
-        display = "Instruction has {} read parameters, but {} ParameterMode values were given",
+        display = "Instruction has {} parameters, but {} ParameterMode values were given",
         _0, _1
     )]
-    ReadModeMismatch(usize, usize),
+    ModeMismatch(usize, usize),
+    #[fail(display = "Cannot patch address {} outside the program", _0)]
+    PatchOutOfRange(usize),
+    #[fail(display = "Program exceeded its limit on executed instructions")]
+    StepLimitExceeded,
+}
+
+/// A parameter value quoted in an error. Values that don't fit in `i128`,
+/// which only `BigInt` cells can hold, are given by sign and bit length.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum Value {
+    Exact(i128),
+    Approximate { negative: bool, bits: usize },
+}
+
+impl From<usize> for Value {
+    fn from(value: usize) -> Self {
+        Value::Exact(value as i128)
+    }
+}
+
+impl From<i64> for Value {
+    fn from(value: i64) -> Self {
+        Value::Exact(value.into())
+    }
+}
+
+impl fmt::Display for Value {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Value::Exact(value) => write!(f, "{}", value),
+            Value::Approximate {
+                negative: false,
+                bits,
+            } => write!(f, "at least 2^{}", bits - 1),
+            Value::Approximate {
+                negative: true,
+                bits,
+            } => write!(f, "at most -2^{}", bits - 1),
+        }
+    }
+}
+
+/// How arithmetic instructions handle results that don't fit in a cell
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub enum OverflowPolicy {
+    /// Fail with `ArithmeticOverflow`
+    #[default]
+    Checked,
+    /// Wrap around at the boundary of the cell type
+    Wrapping,
+    /// Clamp to the cell type's minimum or maximum
+    Saturating,
+}
+
+impl OverflowPolicy {
+    pub fn add<C: Cell>(self, a: &C, b: &C) -> Result<C, ErrorKind> {
+        match self {
+            OverflowPolicy::Checked => a.checked_add(b).ok_or(ErrorKind::ArithmeticOverflow),
+            OverflowPolicy::Wrapping => Ok(Cell::wrapping_add(a, b)),
+            OverflowPolicy::Saturating => Ok(Cell::saturating_add(a, b)),
+        }
+    }
+
+    pub fn multiply<C: Cell>(self, a: &C, b: &C) -> Result<C, ErrorKind> {
+        match self {
+            OverflowPolicy::Checked => a.checked_mul(b).ok_or(ErrorKind::ArithmeticOverflow),
+            OverflowPolicy::Wrapping => Ok(Cell::wrapping_mul(a, b)),
+            OverflowPolicy::Saturating => Ok(Cell::saturating_mul(a, b)),
+        }
+    }
 }
 
-#[derive(Clone, Copy, Debug, PartialEq)]
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
 pub enum ParameterType {
     Read,
     Write,
 }
 
-fn process_parameters<T: super::program::System>(
+// Memory grows on demand, so any non-negative address below the limit is valid
+fn resolve_address<C: Cell>(value: &C) -> Result<usize, ErrorKind> {
+    if *value < C::zero() {
+        return Err(ErrorKind::NegativeAddress(value.to_value()));
+    }
+
+    match value.to_usize() {
+        Some(address) if address < super::program::MAX_MEMORY_LEN => Ok(address),
+        _ => Err(ErrorKind::AddressOutOfRange(value.to_value())),
+    }
+}
+
+fn resolve_relative<C: Cell, T: super::program::System<C> + ?Sized>(
+    system: &T,
+    offset: &C,
+) -> Result<usize, ErrorKind> {
+    let address = offset
+        .to_i64()
+        .and_then(|offset| system.read_relative_base().checked_add(offset))
+        .ok_or(ErrorKind::ArithmeticOverflow)?;
+    resolve_address(&address)
+}
+
+fn process_parameters<C: Cell, T: super::program::System<C> + ?Sized>(
     system: &T,
     param_types: &[ParameterType],
-    read_modes: &[ParameterMode],
-) -> Result<(Vec<i64>, Vec<usize>), ErrorKind> {
+    modes: &[ParameterMode],
+) -> Result<(Vec<C>, Vec<usize>), ErrorKind> {
+    resolve_parameters(system, param_types, modes).map_err(|(_, kind)| kind)
+}
+
+// An error, with the 1-based index of the parameter that caused it if there is one
+type ParameterError = (Option<usize>, ErrorKind);
+
+// Like `process_parameters`, but errors say which parameter caused them
+fn resolve_parameters<C: Cell, T: super::program::System<C> + ?Sized>(
+    system: &T,
+    param_types: &[ParameterType],
+    modes: &[ParameterMode],
+) -> Result<(Vec<C>, Vec<usize>), ParameterError> {
     let instruction_size = 1 + param_types.len();
-    let count_read_params = param_types
-        .iter()
-        .filter(|ty| **ty == ParameterType::Read)
-        .count();
-
-    if read_modes.len() != count_read_params {
-        return Err(ErrorKind::ReadModeMismatch(
-            count_read_params,
-            read_modes.len(),
+
+    if modes.len() != param_types.len() {
+        return Err((
+            None,
+            ErrorKind::ModeMismatch(param_types.len(), modes.len()),
         ));
     }
 
     let address = system.read_instruction_pointer();
     if address + instruction_size > system.get_memory_len() {
-        return Err(ErrorKind::NotEnoughParameters);
+        return Err((None, ErrorKind::NotEnoughParameters));
     }
 
     let address = address + 1; // skip over opcode to the 1st param
 
-    let mut read_iter = read_modes.iter();
-    let mut read_values = Vec::<i64>::new();
+    let mut read_values = Vec::<C>::new();
     let mut write_addrs = Vec::<usize>::new();
-    for (index, param) in param_types.iter().enumerate() {
-        match param {
-            ParameterType::Read => {
-                let mode = *read_iter
-                    .next()
-                    .expect("Read modes don't align with actual parameters");
-                match mode {
-                    ParameterMode::Position => {
-                        let address = system.read_memory(address + index) as usize;
-                        if address > system.get_memory_len() {
-                            return Err(ErrorKind::AddressOutOfRange(address));
-                        } else {
-                            read_values.push(system.read_memory(address));
-                        }
-                    }
-                    ParameterMode::Immediate => {
-                        read_values.push(system.read_memory(address + index))
-                    }
-                };
+    for (index, (param, mode)) in param_types.iter().zip(modes).enumerate() {
+        let at = |kind| (Some(index + 1), kind);
+        let value = system.read_memory(address + index).map_err(at)?;
+        match (param, mode) {
+            (ParameterType::Read, ParameterMode::Position) => {
+                let address = resolve_address(&value).map_err(at)?;
+                read_values.push(system.read_memory(address).map_err(at)?);
+            }
+            (ParameterType::Read, ParameterMode::Immediate) => read_values.push(value),
+            (ParameterType::Read, ParameterMode::Relative) => {
+                let address = resolve_relative(system, &value).map_err(at)?;
+                read_values.push(system.read_memory(address).map_err(at)?);
+            }
+            (ParameterType::Write, ParameterMode::Position) => {
+                write_addrs.push(resolve_address(&value).map_err(at)?);
+            }
+            (ParameterType::Write, ParameterMode::Immediate) => {
+                return Err(at(ErrorKind::InvalidParameterMode(index + 1)));
             }
-            ParameterType::Write => {
-                let address = system.read_memory(address + index) as usize;
-                if address > system.get_memory_len() {
-                    return Err(ErrorKind::AddressOutOfRange(address));
-                }
-                write_addrs.push(address);
+            (ParameterType::Write, ParameterMode::Relative) => {
+                write_addrs.push(resolve_relative(system, &value).map_err(at)?);
             }
         }
     }
@@ -169,9 +202,32 @@ fn process_parameters<T: super::program::System>(
     Ok((read_values, write_addrs))
 }
 
-pub fn add<T: super::program::System>(
+/// Finds the 1-based index of the first parameter of the instruction at the
+/// instruction pointer that can't be resolved, given the type and mode of
+/// every parameter
+pub fn faulting_operand<C: Cell, T: super::program::System<C> + ?Sized>(
+    system: &T,
+    parameters: &[(ParameterType, ParameterMode)],
+) -> Option<usize> {
+    let (types, modes): (Vec<_>, Vec<_>) = parameters.iter().copied().unzip();
+    resolve_parameters(system, &types, &modes)
+        .err()
+        .and_then(|(index, _)| index)
+}
+
+/// Resolves the values of the read parameters of the instruction at the
+/// instruction pointer, given the type and mode of every parameter
+pub fn read_operands<C: Cell, T: super::program::System<C> + ?Sized>(
+    system: &T,
+    parameters: &[(ParameterType, ParameterMode)],
+) -> Result<Vec<C>, ErrorKind> {
+    let (types, modes): (Vec<_>, Vec<_>) = parameters.iter().copied().unzip();
+    process_parameters(system, &types, &modes).map(|(read_values, _)| read_values)
+}
+
+pub fn add<C: Cell, T: super::program::System<C> + ?Sized>(
     system: &mut T,
-    read_modes: &[ParameterMode],
+    modes: &[ParameterMode],
 ) -> Result<usize, ErrorKind> {
     const INSTRUCTION_SIZE: usize = 4;
     let (read_values, write_addrs) = process_parameters(
@@ -181,15 +237,34 @@ pub fn add<T: super::program::System>(
             ParameterType::Read,
             ParameterType::Write,
         ],
-        read_modes,
+        modes,
     )?;
-    system.write_memory(write_addrs[0], read_values[0] + read_values[1]);
+    let sum = system
+        .overflow_policy()
+        .add(&read_values[0], &read_values[1])?;
+    system.write_memory(write_addrs[0], sum)?;
+    Ok(system.read_instruction_pointer() + INSTRUCTION_SIZE)
+}
+
+pub fn adjust_relative_base<C: Cell, T: super::program::System<C> + ?Sized>(
+    system: &mut T,
+    mode: ParameterMode,
+) -> Result<usize, ErrorKind> {
+    const INSTRUCTION_SIZE: usize = 2;
+    let (read_values, _) = process_parameters(system, &[ParameterType::Read], &[mode])?;
+    let offset = read_values[0]
+        .to_i64()
+        .ok_or(ErrorKind::ArithmeticOverflow)?;
+    let relative_base = system
+        .overflow_policy()
+        .add(&system.read_relative_base(), &offset)?;
+    system.write_relative_base(relative_base);
     Ok(system.read_instruction_pointer() + INSTRUCTION_SIZE)
 }
 
-pub fn equals<T: super::program::System>(
+pub fn equals<C: Cell, T: super::program::System<C> + ?Sized>(
     system: &mut T,
-    read_modes: &[ParameterMode],
+    modes: &[ParameterMode],
 ) -> Result<usize, ErrorKind> {
     const INSTRUCTION_SIZE: usize = 4;
     let (read_values, write_addrs) = process_parameters(
@@ -199,40 +274,43 @@ pub fn equals<T: super::program::System>(
             ParameterType::Read,
             ParameterType::Write,
         ],
-        read_modes,
+        modes,
     )?;
-    let result: i64 = if read_values[0] == read_values[1] {
-        1
+    let result = if read_values[0] == read_values[1] {
+        C::one()
     } else {
-        0
+        C::zero()
     };
-    system.write_memory(write_addrs[0], result);
+    system.write_memory(write_addrs[0], result)?;
     Ok(system.read_instruction_pointer() + INSTRUCTION_SIZE)
 }
 
-pub fn jump_if<T: super::program::System>(
+pub fn jump_if<C: Cell, T: super::program::System<C> + ?Sized>(
     cmp: bool,
     system: &mut T,
-    read_modes: &[ParameterMode],
+    modes: &[ParameterMode],
 ) -> Result<usize, ErrorKind> {
     const INSTRUCTION_SIZE: usize = 3;
-    let (read_values, _) = process_parameters(
-        system,
-        &[ParameterType::Read, ParameterType::Read],
-        read_modes,
-    )?;
-
-    let comparand = read_values[0] != 0;
-    Ok(if cmp == comparand {
-        read_values[1] as usize
+    let (read_values, _) =
+        process_parameters(system, &[ParameterType::Read, ParameterType::Read], modes)?;
+
+    let comparand = !read_values[0].is_zero();
+    if cmp == comparand {
+        let target = &read_values[1];
+        if *target < C::zero() {
+            return Err(ErrorKind::NegativeJump(target.to_value()));
+        }
+        target
+            .to_usize()
+            .ok_or_else(|| ErrorKind::JumpOutOfRange(target.to_value()))
     } else {
-        system.read_instruction_pointer() + INSTRUCTION_SIZE
-    })
+        Ok(system.read_instruction_pointer() + INSTRUCTION_SIZE)
+    }
 }
 
-pub fn less_than<T: super::program::System>(
+pub fn less_than<C: Cell, T: super::program::System<C> + ?Sized>(
     system: &mut T,
-    read_modes: &[ParameterMode],
+    modes: &[ParameterMode],
 ) -> Result<usize, ErrorKind> {
     const INSTRUCTION_SIZE: usize = 4;
     let (read_values, write_addrs) = process_parameters(
@@ -242,20 +320,20 @@ pub fn less_than<T: super::program::System>(
             ParameterType::Read,
             ParameterType::Write,
         ],
-        read_modes,
+        modes,
     )?;
-    let result: i64 = if read_values[0] < read_values[1] {
-        1
+    let result = if read_values[0] < read_values[1] {
+        C::one()
     } else {
-        0
+        C::zero()
     };
-    system.write_memory(write_addrs[0], result);
+    system.write_memory(write_addrs[0], result)?;
     Ok(system.read_instruction_pointer() + INSTRUCTION_SIZE)
 }
 
-pub fn multiply<T: super::program::System>(
+pub fn multiply<C: Cell, T: super::program::System<C> + ?Sized>(
     system: &mut T,
-    read_modes: &[ParameterMode],
+    modes: &[ParameterMode],
 ) -> Result<usize, ErrorKind> {
     const INSTRUCTION_SIZE: usize = 4;
     let (read_values, write_addrs) = process_parameters(
@@ -265,140 +343,59 @@ pub fn multiply<T: super::program::System>(
             ParameterType::Read,
             ParameterType::Write,
         ],
-        read_modes,
+        modes,
     )?;
-    system.write_memory(write_addrs[0], read_values[0] * read_values[1]);
+    let product = system
+        .overflow_policy()
+        .multiply(&read_values[0], &read_values[1])?;
+    system.write_memory(write_addrs[0], product)?;
     Ok(system.read_instruction_pointer() + INSTRUCTION_SIZE)
 }
 
-pub fn print<T: super::program::System>(
+pub fn print<C: Cell, T: super::program::System<C> + ?Sized>(
     system: &mut T,
-    read_mode: ParameterMode,
+    mode: ParameterMode,
 ) -> Result<usize, ErrorKind> {
     const INSTRUCTION_SIZE: usize = 2;
-    let (read_values, _) = process_parameters(system, &[ParameterType::Read], &[read_mode])?;
-    system.write_output(read_values[0]);
+    let (read_values, _) = process_parameters(system, &[ParameterType::Read], &[mode])?;
+    system.write_output(read_values[0].clone());
     Ok(system.read_instruction_pointer() + INSTRUCTION_SIZE)
 }
 
-pub fn store<T: super::program::System>(system: &mut T) -> Result<usize, ErrorKind> {
+pub fn store<C: Cell, T: super::program::System<C> + ?Sized>(
+    system: &mut T,
+    mode: ParameterMode,
+) -> Result<usize, ErrorKind> {
     const INSTRUCTION_SIZE: usize = 2;
-    let (_, write_addrs) = process_parameters(system, &[ParameterType::Write], &[])?;
-    system.write_memory(write_addrs[0], system.read_input());
+    let (_, write_addrs) = process_parameters(system, &[ParameterType::Write], &[mode])?;
+    let value = system.read_input().ok_or(ErrorKind::InputUnavailable)?;
+    system.write_memory(write_addrs[0], value)?;
     Ok(system.read_instruction_pointer() + INSTRUCTION_SIZE)
 }
 
 #[cfg(test)]
 mod tests {
-    mod opcode {
-        use super::super::*;
-
-        #[test]
-        fn can_parse_addition() {
-            assert_eq!(
-                Opcode::Addition {
-                    param1: ParameterMode::Position,
-                    param2: ParameterMode::Position
-                },
-                Opcode::parse(1).unwrap()
-            );
-
-            assert_eq!(
-                Opcode::Addition {
-                    param1: ParameterMode::Immediate,
-                    param2: ParameterMode::Position
-                },
-                Opcode::parse(101).unwrap()
-            );
-
-            assert_eq!(
-                Opcode::Addition {
-                    param1: ParameterMode::Position,
-                    param2: ParameterMode::Immediate,
-                },
-                Opcode::parse(1001).unwrap()
-            );
-
-            assert_eq!(
-                Opcode::Addition {
-                    param1: ParameterMode::Immediate,
-                    param2: ParameterMode::Immediate,
-                },
-                Opcode::parse(1101).unwrap()
-            );
-        }
-
-        #[test]
-        fn can_parse_halt() {
-            assert_eq!(Opcode::Halt, Opcode::parse(99).unwrap());
-        }
-
-        #[test]
-        fn can_parse_multiply() {
-            assert_eq!(
-                Opcode::Multiplication {
-                    param1: ParameterMode::Position,
-                    param2: ParameterMode::Position
-                },
-                Opcode::parse(2).unwrap()
-            );
-
-            assert_eq!(
-                Opcode::Multiplication {
-                    param1: ParameterMode::Immediate,
-                    param2: ParameterMode::Position
-                },
-                Opcode::parse(102).unwrap()
-            );
-
-            assert_eq!(
-                Opcode::Multiplication {
-                    param1: ParameterMode::Position,
-                    param2: ParameterMode::Immediate,
-                },
-                Opcode::parse(1002).unwrap()
-            );
-
-            assert_eq!(
-                Opcode::Multiplication {
-                    param1: ParameterMode::Immediate,
-                    param2: ParameterMode::Immediate,
-                },
-                Opcode::parse(1102).unwrap()
-            );
-        }
-
-        #[test]
-        fn can_parse_print() {
-            assert_eq!(
-                Opcode::Print {
-                    param: ParameterMode::Position,
-                },
-                Opcode::parse(4).unwrap()
-            );
-
-            assert_eq!(
-                Opcode::Print {
-                    param: ParameterMode::Immediate,
-                },
-                Opcode::parse(104).unwrap()
-            );
-        }
-
-        #[test]
-        fn can_parse_store() {
-            assert_eq!(Opcode::Store, Opcode::parse(3).unwrap());
-        }
-    }
-
     mod instruction {
         use super::super::{super::program::System, *};
 
         const DUMMY_OPCODE: i64 = 88;
+        const OUT_OF_RANGE: i64 = crate::intcode::program::MAX_MEMORY_LEN as i64;
 
         fn test<T: System>(
             system: &mut T,
             read_modes: &[ParameterMode],
+        ) -> Result<(Vec<i64>, Vec<usize>), ErrorKind> {
+            test_with_write_modes(
+                system,
+                read_modes,
+                &[ParameterMode::Position, ParameterMode::Position],
+            )
+        }
+
+        fn test_with_write_modes<T: System>(
+            system: &mut T,
+            read_modes: &[ParameterMode],
+            write_modes: &[ParameterMode],
         ) -> Result<(Vec<i64>, Vec<usize>), ErrorKind> {
             process_parameters(
                 system,
@@ -408,32 +405,39 @@ mod tests {
                     ParameterType::Read,
                     ParameterType::Write,
                 ],
-                read_modes,
+                &[read_modes[0], write_modes[0], read_modes[1], write_modes[1]],
             )
         }
 
         struct TestSystem {
             pub memory: Vec<i64>,
+            pub relative_base: i64,
         }
 
         impl System for TestSystem {
             fn get_memory_len(&self) -> usize {
                 self.memory.len()
             }
-            fn read_memory(&self, address: usize) -> i64 {
-                self.memory[address]
+            fn read_memory(&self, address: usize) -> Result<i64, ErrorKind> {
+                Ok(self.memory.get(address).copied().unwrap_or(0))
             }
             fn read_instruction_pointer(&self) -> usize {
                 0
             }
+            fn read_relative_base(&self) -> i64 {
+                self.relative_base
+            }
 
-            fn read_input(&self) -> i64 {
+            fn read_input(&mut self) -> Option<i64> {
+                unimplemented!()
+            }
+            fn write_memory(&mut self, _: usize, _: i64) -> Result<(), ErrorKind> {
                 unimplemented!()
             }
-            fn write_memory(&mut self, _: usize, _: i64) {
+            fn write_instruction_pointer(&mut self, _: usize) -> Result<(), ErrorKind> {
                 unimplemented!()
             }
-            fn write_instruction_pointer(&mut self, _: usize) {
+            fn write_relative_base(&mut self, _: i64) {
                 unimplemented!()
             }
             fn write_output(&mut self, _: i64) {
@@ -444,11 +448,12 @@ mod tests {
         #[test]
         fn parsing_fails_when_first_input_position_is_out_of_range() {
             let mut system = TestSystem {
-                memory: vec![DUMMY_OPCODE, 5555, 6, 7, 8, 10, 20, 30, 40],
+                memory: vec![DUMMY_OPCODE, OUT_OF_RANGE, 6, 7, 8, 10, 20, 30, 40],
+                relative_base: 0,
             };
 
             assert_eq!(
-                Err(ErrorKind::AddressOutOfRange(5555)),
+                Err(ErrorKind::AddressOutOfRange(OUT_OF_RANGE.into())),
                 test(
                     &mut system,
                     &[ParameterMode::Position, ParameterMode::Position]
@@ -459,11 +464,12 @@ mod tests {
         #[test]
         fn parsing_fails_when_second_input_position_is_out_of_range() {
             let mut system = TestSystem {
-                memory: vec![DUMMY_OPCODE, 5, 5555, 7, 8, 10, 20, 30, 40],
+                memory: vec![DUMMY_OPCODE, 5, OUT_OF_RANGE, 7, 8, 10, 20, 30, 40],
+                relative_base: 0,
             };
 
             assert_eq!(
-                Err(ErrorKind::AddressOutOfRange(5555)),
+                Err(ErrorKind::AddressOutOfRange(OUT_OF_RANGE.into())),
                 test(
                     &mut system,
                     &[ParameterMode::Position, ParameterMode::Position]
@@ -474,11 +480,12 @@ mod tests {
         #[test]
         fn parsing_fails_when_first_output_position_is_out_of_range() {
             let mut system = TestSystem {
-                memory: vec![DUMMY_OPCODE, 5, 6, 5555, 8, 10, 20, 30, 40],
+                memory: vec![DUMMY_OPCODE, 5, 6, OUT_OF_RANGE, 8, 10, 20, 30, 40],
+                relative_base: 0,
             };
 
             assert_eq!(
-                Err(ErrorKind::AddressOutOfRange(5555)),
+                Err(ErrorKind::AddressOutOfRange(OUT_OF_RANGE.into())),
                 test(
                     &mut system,
                     &[ParameterMode::Position, ParameterMode::Position]
@@ -489,11 +496,44 @@ mod tests {
         #[test]
         fn parsing_fails_when_second_output_position_is_out_of_range() {
             let mut system = TestSystem {
-                memory: vec![DUMMY_OPCODE, 5, 6, 7, 5555, 10, 20, 30, 40],
+                memory: vec![DUMMY_OPCODE, 5, 6, 7, OUT_OF_RANGE, 10, 20, 30, 40],
+                relative_base: 0,
+            };
+
+            assert_eq!(
+                Err(ErrorKind::AddressOutOfRange(OUT_OF_RANGE.into())),
+                test(
+                    &mut system,
+                    &[ParameterMode::Position, ParameterMode::Position]
+                )
+            );
+        }
+
+        #[test]
+        fn parsing_fails_when_input_position_is_negative() {
+            let mut system = TestSystem {
+                memory: vec![DUMMY_OPCODE, 5, 6, -7, 8, 10, 20, 30, 40],
+                relative_base: 0,
+            };
+
+            assert_eq!(
+                Err(ErrorKind::NegativeAddress(Value::Exact(-7))),
+                test(
+                    &mut system,
+                    &[ParameterMode::Position, ParameterMode::Position]
+                )
+            );
+        }
+
+        #[test]
+        fn parsing_fails_when_output_position_is_negative() {
+            let mut system = TestSystem {
+                memory: vec![DUMMY_OPCODE, 5, 6, 7, -8, 10, 20, 30, 40],
+                relative_base: 0,
             };
 
             assert_eq!(
-                Err(ErrorKind::AddressOutOfRange(5555)),
+                Err(ErrorKind::NegativeAddress(Value::Exact(-8))),
                 test(
                     &mut system,
                     &[ParameterMode::Position, ParameterMode::Position]
@@ -501,10 +541,30 @@ mod tests {
             );
         }
 
+        #[test]
+        fn parsing_positions_beyond_the_end_of_memory_yields_zero_values() {
+            let mut system = TestSystem {
+                memory: vec![DUMMY_OPCODE, 100, 200, 300, 400],
+                relative_base: 0,
+            };
+
+            let result = test(
+                &mut system,
+                &[ParameterMode::Position, ParameterMode::Position],
+            );
+            assert!(result.is_ok());
+
+            let (read_values, write_addrs) = result.unwrap();
+
+            assert_eq!(&[0, 0], &read_values[..]);
+            assert_eq!(&[200, 400], &write_addrs[..]);
+        }
+
         #[test]
         fn parsing_fails_when_there_are_not_enough_parameters() {
             let mut system = TestSystem {
                 memory: vec![DUMMY_OPCODE, 5, 6, 7],
+                relative_base: 0,
             };
 
             assert_eq!(
@@ -520,6 +580,7 @@ mod tests {
         fn parsing_position_parameters_yields_the_right_values() {
             let mut system = TestSystem {
                 memory: vec![DUMMY_OPCODE, 5, 6, 7, 8, 10, 20, 30, 40],
+                relative_base: 0,
             };
 
             let result = test(
@@ -533,5 +594,112 @@ mod tests {
             assert_eq!(&[10, 30], &read_values[..]);
             assert_eq!(&[6, 8], &write_addrs[..]);
         }
+
+        #[test]
+        fn parsing_relative_parameters_yields_the_right_values() {
+            let mut system = TestSystem {
+                memory: vec![DUMMY_OPCODE, 1, 2, 3, -1, 10, 20, 30, 40],
+                relative_base: 4,
+            };
+
+            let result = test_with_write_modes(
+                &mut system,
+                &[ParameterMode::Relative, ParameterMode::Relative],
+                &[ParameterMode::Relative, ParameterMode::Relative],
+            );
+            assert!(result.is_ok());
+
+            let (read_values, write_addrs) = result.unwrap();
+
+            assert_eq!(&[10, 30], &read_values[..]);
+            assert_eq!(&[6, 3], &write_addrs[..]);
+        }
+
+        #[test]
+        fn parsing_fails_when_relative_input_position_is_negative() {
+            let mut system = TestSystem {
+                memory: vec![DUMMY_OPCODE, -5, 6, 7, 8, 10, 20, 30, 40],
+                relative_base: 4,
+            };
+
+            assert_eq!(
+                Err(ErrorKind::NegativeAddress(Value::Exact(-1))),
+                test(
+                    &mut system,
+                    &[ParameterMode::Relative, ParameterMode::Position]
+                )
+            );
+        }
+
+        #[test]
+        fn parsing_fails_when_relative_position_overflows() {
+            let mut system = TestSystem {
+                memory: vec![DUMMY_OPCODE, i64::MAX, 6, 7, 8, 10, 20, 30, 40],
+                relative_base: 1,
+            };
+
+            assert_eq!(
+                Err(ErrorKind::ArithmeticOverflow),
+                test(
+                    &mut system,
+                    &[ParameterMode::Relative, ParameterMode::Position]
+                )
+            );
+        }
+
+        #[test]
+        fn parsing_fails_when_output_parameter_is_in_immediate_mode() {
+            let mut system = TestSystem {
+                memory: vec![DUMMY_OPCODE, 5, 6, 7, 8, 10, 20, 30, 40],
+                relative_base: 0,
+            };
+
+            assert_eq!(
+                Err(ErrorKind::InvalidParameterMode(2)),
+                test_with_write_modes(
+                    &mut system,
+                    &[ParameterMode::Position, ParameterMode::Position],
+                    &[ParameterMode::Immediate, ParameterMode::Position],
+                )
+            );
+        }
+    }
+
+    mod overflow_policy {
+        use super::super::*;
+
+        #[test]
+        fn checked_arithmetic_fails_on_overflow() {
+            let policy = OverflowPolicy::Checked;
+            assert_eq!(Ok(5), policy.add(&2, &3));
+            assert_eq!(
+                Err(ErrorKind::ArithmeticOverflow),
+                policy.add(&i64::MAX, &1)
+            );
+            assert_eq!(Ok(-6), policy.multiply(&2, &-3));
+            assert_eq!(
+                Err(ErrorKind::ArithmeticOverflow),
+                policy.multiply(&i64::MIN, &-1)
+            );
+        }
+
+        #[test]
+        fn wrapping_arithmetic_wraps_around() {
+            let policy = OverflowPolicy::Wrapping;
+            assert_eq!(Ok(i64::MIN), policy.add(&i64::MAX, &1));
+            assert_eq!(Ok(-2), policy.multiply(&i64::MAX, &2));
+        }
+
+        #[test]
+        fn saturating_arithmetic_clamps() {
+            let policy = OverflowPolicy::Saturating;
+            assert_eq!(Ok(i64::MAX), policy.add(&i64::MAX, &1));
+            assert_eq!(Ok(i64::MIN), policy.multiply(&i64::MAX, &-2));
+        }
+
+        #[test]
+        fn defaults_to_checked() {
+            assert_eq!(OverflowPolicy::Checked, OverflowPolicy::default());
+        }
     }
 }
@@ -0,0 +1,276 @@
+use std::collections::VecDeque;
+use std::fmt::Display;
+use std::io::{self, BufRead, Write};
+use std::sync::mpsc::{Receiver, Sender, SyncSender};
+
+/// A source of values for a program's input instructions, which are `i64`
+/// unless the program uses another cell type
+pub trait IntcodeInput<C = i64> {
+    /// Returns the next value, or `None` if no input is available
+    fn read_input(&mut self) -> Option<C>;
+}
+
+/// A destination for the values of a program's output instructions
+pub trait IntcodeOutput<C = i64> {
+    fn write_output(&mut self, value: C);
+}
+
+impl<C, T: IntcodeInput<C> + ?Sized> IntcodeInput<C> for &mut T {
+    fn read_input(&mut self) -> Option<C> {
+        (**self).read_input()
+    }
+}
+
+impl<C, T: IntcodeOutput<C> + ?Sized> IntcodeOutput<C> for &mut T {
+    fn write_output(&mut self, value: C) {
+        (**self).write_output(value)
+    }
+}
+
+/// No input is ever available
+impl<C> IntcodeInput<C> for () {
+    fn read_input(&mut self) -> Option<C> {
+        None
+    }
+}
+
+/// Output is discarded
+impl<C> IntcodeOutput<C> for () {
+    fn write_output(&mut self, _: C) {}
+}
+
+impl<C> IntcodeInput<C> for VecDeque<C> {
+    fn read_input(&mut self) -> Option<C> {
+        self.pop_front()
+    }
+}
+
+impl<C> IntcodeOutput<C> for VecDeque<C> {
+    fn write_output(&mut self, value: C) {
+        self.push_back(value);
+    }
+}
+
+impl<C> IntcodeOutput<C> for Vec<C> {
+    fn write_output(&mut self, value: C) {
+        self.push(value);
+    }
+}
+
+/// Blocks until a value is sent; input ends when every sender has been dropped
+impl<C> IntcodeInput<C> for Receiver<C> {
+    fn read_input(&mut self) -> Option<C> {
+        self.recv().ok()
+    }
+}
+
+/// Values sent after the receiver has been dropped are discarded
+impl<C> IntcodeOutput<C> for Sender<C> {
+    fn write_output(&mut self, value: C) {
+        let _ = self.send(value);
+    }
+}
+
+impl<C> IntcodeOutput<C> for SyncSender<C> {
+    fn write_output(&mut self, value: C) {
+        let _ = self.send(value);
+    }
+}
+
+/// Adapts a closure that produces input values
+pub struct FnInput<F>(pub F);
+
+impl<C, F: FnMut() -> C> IntcodeInput<C> for FnInput<F> {
+    fn read_input(&mut self) -> Option<C> {
+        Some((self.0)())
+    }
+}
+
+/// Adapts a closure that consumes output values
+pub struct FnOutput<F>(pub F);
+
+impl<C, F: FnMut(C)> IntcodeOutput<C> for FnOutput<F> {
+    fn write_output(&mut self, value: C) {
+        (self.0)(value)
+    }
+}
+
+/// Adapts an iterator of input values
+pub struct IterInput<T>(pub T);
+
+impl<C, T: Iterator<Item = C>> IntcodeInput<C> for IterInput<T> {
+    fn read_input(&mut self) -> Option<C> {
+        self.0.next()
+    }
+}
+
+/// Reads input values separated by whitespace or commas, a line at a time.
+/// Input ends at end of file or at the first read or parse error, which is kept
+/// for inspection.
+pub struct ReaderInput<R: BufRead> {
+    reader: R,
+    pending: VecDeque<i64>,
+    error: Option<io::Error>,
+}
+
+impl<R: BufRead> ReaderInput<R> {
+    pub fn new(reader: R) -> Self {
+        ReaderInput {
+            reader,
+            pending: VecDeque::new(),
+            error: None,
+        }
+    }
+
+    pub fn error(&self) -> Option<&io::Error> {
+        self.error.as_ref()
+    }
+}
+
+impl<R: BufRead> IntcodeInput for ReaderInput<R> {
+    fn read_input(&mut self) -> Option<i64> {
+        while self.pending.is_empty() && self.error.is_none() {
+            let mut line = String::new();
+            match self.reader.read_line(&mut line) {
+                Ok(0) => return None,
+                Ok(_) => {
+                    for token in line.split(|c: char| c == ',' || c.is_whitespace()) {
+                        if token.is_empty() {
+                            continue;
+                        }
+                        match token.parse::<i64>() {
+                            Ok(value) => self.pending.push_back(value),
+                            Err(e) => {
+                                self.error = Some(io::Error::new(io::ErrorKind::InvalidData, e));
+                                break;
+                            }
+                        }
+                    }
+                }
+                Err(e) => self.error = Some(e),
+            }
+        }
+
+        self.pending.pop_front()
+    }
+}
+
+/// Writes each output value on its own line. The first write error is kept
+/// for inspection and later values are discarded.
+pub struct WriterOutput<W: Write> {
+    writer: W,
+    error: Option<io::Error>,
+}
+
+impl<W: Write> WriterOutput<W> {
+    pub fn new(writer: W) -> Self {
+        WriterOutput {
+            writer,
+            error: None,
+        }
+    }
+
+    pub fn error(&self) -> Option<&io::Error> {
+        self.error.as_ref()
+    }
+
+    pub fn into_inner(self) -> W {
+        self.writer
+    }
+}
+
+impl<C: Display, W: Write> IntcodeOutput<C> for WriterOutput<W> {
+    fn write_output(&mut self, value: C) {
+        if self.error.is_none() {
+            if let Err(e) = writeln!(self.writer, "{}", value).and_then(|_| self.writer.flush()) {
+                self.error = Some(e);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::mpsc;
+
+    fn read_all<T: IntcodeInput>(mut input: T) -> Vec<i64> {
+        std::iter::from_fn(|| input.read_input()).collect()
+    }
+
+    #[test]
+    fn unit_has_no_input_and_discards_output() {
+        assert_eq!(None, IntcodeInput::<i64>::read_input(&mut ()));
+        ().write_output(5);
+    }
+
+    #[test]
+    fn queue_supplies_and_collects_values_in_order() {
+        let mut queue: VecDeque<i64> = vec![1, 2].into();
+        queue.write_output(3);
+        assert_eq!(vec![1, 2, 3], read_all(queue));
+    }
+
+    #[test]
+    fn vec_collects_output() {
+        let mut output = Vec::new();
+        output.write_output(1);
+        output.write_output(2);
+        assert_eq!(vec![1, 2], output);
+    }
+
+    #[test]
+    fn iterator_supplies_values_until_exhausted() {
+        assert_eq!(vec![1, 2, 3], read_all(IterInput(1..=3)));
+    }
+
+    #[test]
+    fn closures_supply_and_collect_values() {
+        let mut next = 0;
+        let mut input = FnInput(|| {
+            next += 1;
+            next
+        });
+        assert_eq!(Some(1), input.read_input());
+        assert_eq!(Some(2), input.read_input());
+
+        let mut collected = Vec::new();
+        FnOutput(|value| collected.push(value)).write_output(7);
+        assert_eq!(vec![7], collected);
+    }
+
+    #[test]
+    fn channels_carry_values_until_disconnected() {
+        let (mut sender, receiver) = mpsc::channel();
+        sender.write_output(4);
+        sender.write_output(5);
+        drop(sender);
+        assert_eq!(vec![4, 5], read_all(receiver));
+    }
+
+    #[test]
+    fn reader_parses_values_across_lines() {
+        let input = ReaderInput::new("1, 2\n\n-3 4\n".as_bytes());
+        assert_eq!(vec![1, 2, -3, 4], read_all(input));
+    }
+
+    #[test]
+    fn reader_stops_at_invalid_value_and_keeps_the_error() {
+        let mut input = ReaderInput::new("1 x 2\n3\n".as_bytes());
+        assert_eq!(Some(1), input.read_input());
+        assert_eq!(None, input.read_input());
+        assert_eq!(
+            io::ErrorKind::InvalidData,
+            input.error().map(|e| e.kind()).unwrap()
+        );
+    }
+
+    #[test]
+    fn writer_writes_a_line_per_value() {
+        let mut output = WriterOutput::new(Vec::<u8>::new());
+        output.write_output(12);
+        output.write_output(-3);
+        assert!(output.error().is_none());
+        assert_eq!("12\n-3\n", String::from_utf8(output.into_inner()).unwrap());
+    }
+}
@@ -0,0 +1,131 @@
+use std::{fs, io, path::Path};
+
+#[derive(Debug, Fail)]
+pub enum Error {
+    #[fail(display = "Failed to read the program")]
+    Io(#[cause] io::Error),
+    #[fail(display = "Value {} ('{}') is not a valid integer", index, token)]
+    InvalidToken { index: usize, token: String },
+    #[fail(display = "Value {} is missing between two commas", index)]
+    EmptyValue { index: usize },
+}
+
+impl From<io::Error> for Error {
+    fn from(e: io::Error) -> Self {
+        Error::Io(e)
+    }
+}
+
+/// Parses a memory image from comma-separated integers. Whitespace and newlines
+/// may appear anywhere between values, and `#` or `;` starts a comment that runs
+/// to the end of the line. A single trailing comma is allowed, but an empty
+/// value between commas is an error, since it would shift every later address.
+pub fn parse(text: &str) -> Result<Vec<i64>, Error> {
+    let mut values = Vec::new();
+    // Whether a value must come before the next comma
+    let mut needs_value = true;
+
+    for line in text.lines() {
+        let line = line.split(['#', ';']).next().unwrap_or("");
+        for (field_index, field) in line.split(',').enumerate() {
+            if field_index > 0 {
+                if needs_value {
+                    return Err(Error::EmptyValue {
+                        index: values.len(),
+                    });
+                }
+                needs_value = true;
+            }
+
+            for token in field.split_whitespace() {
+                let value = token.parse::<i64>().map_err(|_| Error::InvalidToken {
+                    index: values.len(),
+                    token: token.to_string(),
+                })?;
+                values.push(value);
+                needs_value = false;
+            }
+        }
+    }
+
+    Ok(values)
+}
+
+/// Reads and parses a memory image from a file
+pub fn from_file<P: AsRef<Path>>(path: P) -> Result<Vec<i64>, Error> {
+    parse(&fs::read_to_string(path)?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_comma_separated_values() {
+        assert_eq!(vec![1, 0, -3, 99], parse("1,0,-3,99").unwrap());
+    }
+
+    #[test]
+    fn parses_an_empty_program() {
+        assert!(parse("").unwrap().is_empty());
+        assert!(parse(" \n\n").unwrap().is_empty());
+    }
+
+    #[test]
+    fn tolerates_whitespace_newlines_and_trailing_commas() {
+        assert_eq!(
+            vec![1, 2, 3, 4, 99],
+            parse(" 1, 2,\n\t3 ,4,\r\n99,\n").unwrap()
+        );
+    }
+
+    #[test]
+    fn ignores_comments() {
+        let text = "# day 2 example\n1,9,10,3, ; add\n2,3,11,0, # multiply\n99";
+        assert_eq!(vec![1, 9, 10, 3, 2, 3, 11, 0, 99], parse(text).unwrap());
+    }
+
+    #[test]
+    fn reports_the_index_of_an_invalid_token() {
+        match parse("1,2,\n3,x4,5") {
+            Err(Error::InvalidToken { index, token }) => {
+                assert_eq!(3, index);
+                assert_eq!("x4", token);
+            }
+            result => panic!("unexpected result {:?}", result),
+        }
+    }
+
+    #[test]
+    fn rejects_empty_values() {
+        for (text, missing) in &[
+            ("1,,2", 1),
+            ("1,\n  ,2", 1),
+            (",1", 0),
+            ("1,2, # note\n,", 2),
+        ] {
+            match parse(text) {
+                Err(Error::EmptyValue { index }) => {
+                    assert_eq!(*missing, index, "parsing {:?}", text)
+                }
+                result => panic!("unexpected result {:?}", result),
+            }
+        }
+    }
+
+    #[test]
+    fn reports_values_that_are_out_of_range() {
+        match parse("99999999999999999999") {
+            Err(Error::InvalidToken { index: 0, .. }) => {}
+            result => panic!("unexpected result {:?}", result),
+        }
+    }
+
+    #[test]
+    fn fails_to_read_a_missing_file() {
+        match from_file("src/intcode/no-such-program.txt") {
+            Err(Error::Io(e)) => assert_eq!(io::ErrorKind::NotFound, e.kind()),
+            result => panic!("unexpected result {:?}", result),
+        }
+    }
+}
@@ -1,3 +1,17 @@
+pub mod amplifier;
+pub mod ascii;
+pub mod assembler;
+pub mod cell;
+pub mod control_flow;
+pub mod disassembler;
+pub mod image;
 pub mod instruction;
+pub mod io;
+pub mod load;
+pub mod network;
 #[macro_use]
 pub mod program;
+pub mod registry;
+pub mod search;
+pub mod snapshot;
+pub mod trace;
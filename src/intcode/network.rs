@@ -0,0 +1,466 @@
+use super::io::{IntcodeInput, IntcodeOutput};
+use super::program::{self, Program};
+use std::convert::TryFrom;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::{self, Receiver, Sender};
+use std::thread;
+use std::time::Duration;
+
+/// Packets sent to this address go to the network's monitor
+pub const NAT_ADDRESS: i64 = 255;
+
+/// How long a machine waits for a packet before it reads -1 instead
+const POLL_INTERVAL: Duration = Duration::from_millis(1);
+
+/// How many times in a row a machine must find its queue empty, without
+/// sending anything in between, before it counts as idle
+const IDLE_POLLS: u32 = 2;
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct Packet {
+    pub destination: i64,
+    pub x: i64,
+    pub y: i64,
+}
+
+/// What the router should do after consulting the monitor
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum Control<T> {
+    Continue,
+    /// Deliver a packet to one of the machines
+    Send(Packet),
+    /// Shut the network down, returning a result from `run`
+    Stop(T),
+}
+
+/// Watches the network on behalf of `NAT_ADDRESS`
+pub trait Monitor {
+    type Output;
+
+    /// Called with each packet sent to `NAT_ADDRESS`
+    fn receive(&mut self, packet: Packet) -> Control<Self::Output>;
+
+    /// Called when every running machine is waiting for input and no packets
+    /// are in flight
+    fn idle(&mut self) -> Control<Self::Output>;
+}
+
+/// Stops the network with the first packet sent to `NAT_ADDRESS`
+#[derive(Clone, Copy, Debug, Default)]
+pub struct FirstPacket;
+
+impl Monitor for FirstPacket {
+    type Output = Packet;
+
+    fn receive(&mut self, packet: Packet) -> Control<Packet> {
+        Control::Stop(packet)
+    }
+
+    fn idle(&mut self) -> Control<Packet> {
+        Control::Continue
+    }
+}
+
+/// Keeps the last packet sent to `NAT_ADDRESS` and resends it to address 0
+/// whenever the network is idle. Stops with its `y` value once it would send
+/// the same `y` twice in a row.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct Nat {
+    last_received: Option<Packet>,
+    last_sent_y: Option<i64>,
+}
+
+impl Monitor for Nat {
+    type Output = i64;
+
+    fn receive(&mut self, packet: Packet) -> Control<i64> {
+        self.last_received = Some(packet);
+        Control::Continue
+    }
+
+    fn idle(&mut self) -> Control<i64> {
+        match self.last_received {
+            None => Control::Continue,
+            Some(packet) if self.last_sent_y == Some(packet.y) => Control::Stop(packet.y),
+            Some(packet) => {
+                self.last_sent_y = Some(packet.y);
+                Control::Send(Packet {
+                    destination: 0,
+                    ..packet
+                })
+            }
+        }
+    }
+}
+
+#[derive(Clone, Debug, Eq, Fail, PartialEq)]
+pub enum Error {
+    #[fail(display = "Machine {} failed: {}", _0, _1)]
+    Program(usize, #[cause] program::Error),
+    #[fail(display = "Packet sent to unknown address {}", _0)]
+    UnknownDestination(i64),
+    #[fail(display = "Every machine stopped before the monitor stopped the network")]
+    AllStopped,
+}
+
+/// Boots `size` copies of `memory`, each on its own thread, and routes the
+/// packets they send between them until `monitor` stops the network. Each
+/// machine first reads its address, then reads the `x` and `y` of each packet
+/// sent to it, or -1 when none is waiting. It sends a packet by writing its
+/// destination, `x` and `y`.
+pub fn run<M: Monitor>(memory: &[i64], size: usize, mut monitor: M) -> Result<M::Output, Error> {
+    let shutdown = AtomicBool::new(false);
+    let (events, router_events) = mpsc::channel();
+
+    thread::scope(|scope| {
+        let shutdown = &shutdown;
+        let mut machines = Vec::with_capacity(size);
+        for address in 0..size {
+            let (packets, receiver) = mpsc::channel();
+            let input = NicInput {
+                address,
+                booted: false,
+                packets: receiver,
+                pending_y: None,
+                received: 0,
+                events: events.clone(),
+                shutdown,
+            };
+            let output = NicOutput {
+                address,
+                buffer: Vec::with_capacity(3),
+                events: events.clone(),
+            };
+            let events = events.clone();
+            scope.spawn(move || {
+                let mut machine = Program::with_channels(memory, input, output);
+                // Checking between instructions also stops a machine that never
+                // reads input
+                let result = loop {
+                    if shutdown.load(Ordering::Relaxed) {
+                        break Ok(());
+                    }
+                    match machine.step() {
+                        Ok(None) => {}
+                        Ok(Some(_)) => break Ok(()),
+                        Err(e) => break Err(e),
+                    }
+                };
+                let _ = events.send(Event::Stopped(address, result));
+            });
+            machines.push(packets);
+        }
+        drop(events);
+
+        let result = Router::new(machines).route(&router_events, &mut monitor);
+        shutdown.store(true, Ordering::Relaxed);
+        result
+    })
+}
+
+/// What a machine tells the router
+enum Event {
+    Sent(usize, Packet),
+    /// The machine found its queue empty, having received this many packets
+    Waiting(usize, u64),
+    Stopped(usize, Result<(), program::Error>),
+}
+
+struct NicInput<'a> {
+    address: usize,
+    booted: bool,
+    packets: Receiver<Packet>,
+    pending_y: Option<i64>,
+    received: u64,
+    events: Sender<Event>,
+    shutdown: &'a AtomicBool,
+}
+
+/// Ends input once the network shuts down, which stops the machine
+impl IntcodeInput for NicInput<'_> {
+    fn read_input(&mut self) -> Option<i64> {
+        if self.shutdown.load(Ordering::Relaxed) {
+            return None;
+        }
+        if !self.booted {
+            self.booted = true;
+            return Some(self.address as i64);
+        }
+        if let Some(y) = self.pending_y.take() {
+            return Some(y);
+        }
+
+        match self.packets.recv_timeout(POLL_INTERVAL) {
+            Ok(packet) => {
+                self.received += 1;
+                self.pending_y = Some(packet.y);
+                Some(packet.x)
+            }
+            Err(_) => {
+                let _ = self
+                    .events
+                    .send(Event::Waiting(self.address, self.received));
+                Some(-1)
+            }
+        }
+    }
+}
+
+struct NicOutput {
+    address: usize,
+    buffer: Vec<i64>,
+    events: Sender<Event>,
+}
+
+impl IntcodeOutput for NicOutput {
+    fn write_output(&mut self, value: i64) {
+        self.buffer.push(value);
+        if let [destination, x, y] = self.buffer[..] {
+            self.buffer.clear();
+            let packet = Packet { destination, x, y };
+            let _ = self.events.send(Event::Sent(self.address, packet));
+        }
+    }
+}
+
+struct Router {
+    machines: Vec<Sender<Packet>>,
+    /// The number of packets delivered to each machine
+    delivered: Vec<u64>,
+    /// How many times in a row each machine has found its queue empty
+    polls: Vec<u32>,
+    stopped: Vec<bool>,
+}
+
+impl Router {
+    fn new(machines: Vec<Sender<Packet>>) -> Self {
+        let size = machines.len();
+        Router {
+            machines,
+            delivered: vec![0; size],
+            polls: vec![0; size],
+            stopped: vec![false; size],
+        }
+    }
+
+    fn route<M: Monitor>(
+        &mut self,
+        events: &Receiver<Event>,
+        monitor: &mut M,
+    ) -> Result<M::Output, Error> {
+        loop {
+            let control = match events.recv().map_err(|_| Error::AllStopped)? {
+                Event::Sent(from, packet) => {
+                    self.polls[from] = 0;
+                    if packet.destination == NAT_ADDRESS {
+                        monitor.receive(packet)
+                    } else {
+                        self.deliver(packet)?;
+                        Control::Continue
+                    }
+                }
+                Event::Waiting(address, received) => {
+                    if received == self.delivered[address] {
+                        self.polls[address] += 1;
+                    } else {
+                        self.polls[address] = 0;
+                    }
+                    self.check_idle(monitor)
+                }
+                Event::Stopped(address, result) => {
+                    result.map_err(|e| Error::Program(address, e))?;
+                    self.stopped[address] = true;
+                    if self.stopped.iter().all(|stopped| *stopped) {
+                        return Err(Error::AllStopped);
+                    }
+                    self.check_idle(monitor)
+                }
+            };
+
+            match control {
+                Control::Continue => {}
+                Control::Send(packet) => self.deliver(packet)?,
+                Control::Stop(output) => return Ok(output),
+            }
+        }
+    }
+
+    fn check_idle<M: Monitor>(&mut self, monitor: &mut M) -> Control<M::Output> {
+        let idle = self
+            .polls
+            .iter()
+            .zip(&self.stopped)
+            .all(|(polls, stopped)| *stopped || *polls >= IDLE_POLLS);
+        if !idle {
+            return Control::Continue;
+        }
+
+        // Start counting again, so the monitor hears about each idle spell once
+        self.polls.iter_mut().for_each(|polls| *polls = 0);
+        monitor.idle()
+    }
+
+    fn deliver(&mut self, packet: Packet) -> Result<(), Error> {
+        let address = usize::try_from(packet.destination)
+            .ok()
+            .filter(|address| *address < self.machines.len())
+            .ok_or(Error::UnknownDestination(packet.destination))?;
+
+        self.delivered[address] += 1;
+        self.polls[address] = 0;
+        // A machine that has stopped can't receive anything
+        let _ = self.machines[address].send(packet);
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::{assembler::assemble, instruction::ErrorKind};
+    use super::*;
+
+    // Each machine passes packets on to the next with `x` incremented, and the
+    // last one passes them to the NAT. Machine 0 starts things off.
+    const RELAY: &str = "
+                in [addr]
+                add [addr], #1 -> [next]
+                jt [addr], #poll
+                out #1
+                out #0
+                out #42
+        poll:   in [x]
+                eq [x], #-1 -> [t]
+                jt [t], #poll
+                in [y]
+                add [x], #1 -> [x]
+                eq [next], #4 -> [t]
+                jf [t], #send
+                add #255, #0 -> [next]
+        send:   out [next]
+                out [x]
+                out [y]
+                jt #1, #poll
+        addr:   data 0
+        next:   data 0
+        x:      data 0
+        y:      data 0
+        t:      data 0
+    ";
+
+    fn relay() -> Vec<i64> {
+        assemble(RELAY).unwrap()
+    }
+
+    #[test]
+    fn routes_packets_to_the_monitor() {
+        assert_eq!(
+            Ok(Packet {
+                destination: NAT_ADDRESS,
+                x: 3,
+                y: 42
+            }),
+            run(&relay(), 4, FirstPacket)
+        );
+    }
+
+    #[test]
+    fn nat_resends_the_last_packet_when_idle() {
+        assert_eq!(Ok(42), run(&relay(), 4, Nat::default()));
+    }
+
+    #[test]
+    fn nat_stops_when_it_would_repeat_itself() {
+        let mut nat = Nat::default();
+        assert_eq!(Control::Continue, nat.idle());
+
+        let packet = Packet {
+            destination: NAT_ADDRESS,
+            x: 1,
+            y: 2,
+        };
+        assert_eq!(Control::Continue, nat.receive(packet));
+        assert_eq!(
+            Control::Send(Packet {
+                destination: 0,
+                ..packet
+            }),
+            nat.idle()
+        );
+        assert_eq!(Control::Continue, nat.receive(Packet { x: 5, ..packet }));
+        assert_eq!(Control::Stop(2), nat.idle());
+    }
+
+    #[test]
+    fn fails_on_packets_to_unknown_addresses() {
+        let memory = assemble(
+            "
+                in [0]
+                out #7
+                out #0
+                out #0
+        loop:   in [0]
+                jt #1, #loop
+            ",
+        )
+        .unwrap();
+        assert_eq!(
+            Err(Error::UnknownDestination(7)),
+            run(&memory, 2, FirstPacket)
+        );
+    }
+
+    #[test]
+    fn fails_when_a_machine_fails() {
+        match run(&[3, 0, 5555], 2, FirstPacket) {
+            Err(Error::Program(_, e)) => assert_eq!(ErrorKind::InvalidOpcode, e.kind),
+            result => panic!("unexpected result {:?}", result),
+        }
+    }
+
+    #[test]
+    fn stops_machines_that_never_read_input() {
+        let memory = assemble(
+            "
+                in [addr]
+                jt [addr], #spin
+                out #255
+                out #1
+                out #2
+        spin:   jt #1, #spin
+        addr:   data 0
+            ",
+        )
+        .unwrap();
+        assert_eq!(
+            Ok(Packet {
+                destination: NAT_ADDRESS,
+                x: 1,
+                y: 2
+            }),
+            run(&memory, 2, FirstPacket)
+        );
+    }
+
+    #[test]
+    fn stops_the_other_machines_when_one_fails() {
+        let memory = assemble(
+            "
+                in [addr]
+                jt [addr], #fail
+        spin:   jt #1, #spin
+        fail:   data 5555
+        addr:   data 0
+            ",
+        )
+        .unwrap();
+        match run(&memory, 2, Nat::default()) {
+            Err(Error::Program(1, e)) => assert_eq!(ErrorKind::InvalidOpcode, e.kind),
+            result => panic!("unexpected result {:?}", result),
+        }
+    }
+
+    #[test]
+    fn fails_when_every_machine_halts() {
+        assert_eq!(Err(Error::AllStopped), run(&[3, 0, 99], 3, Nat::default()));
+    }
+}
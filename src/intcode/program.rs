@@ -1,84 +1,517 @@
-use super::instruction::{self, Opcode};
+use super::cell::Cell;
+use super::disassembler;
+use super::instruction::{self, OverflowPolicy, ParameterMode};
+use super::io::{FnInput, FnOutput, IntcodeInput, IntcodeOutput};
+use super::registry::{Decoded, Flow, InstructionSet};
+use super::snapshot::{self, Snapshot};
+use super::trace::{TraceEvent, Tracer};
+use failure::Fail;
+use std::collections::{BTreeSet, VecDeque};
+use std::fmt;
+use std::sync::Arc;
+
+/// What's needed to undo one executed instruction
+struct UndoRecord<C> {
+    address: usize,
+    relative_base: i64,
+    memory_len: usize,
+    /// Each cell the instruction wrote, with the value it held before
+    writes: Vec<(usize, C)>,
+    input: Option<C>,
+}
+
+/// Why `Program::run_until_blocked` returned control to the caller
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum RunState<C = i64> {
+    NeedsInput,
+    Output(C),
+    Halted,
+}
 
-pub struct Program<I, O>
+/// Why `Program::run` or `Program::step` stopped executing
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum Stopped {
+    Halted,
+    /// The next instruction reads input and none is available
+    NeedsInput,
+    /// The next instruction to execute is at a breakpoint address
+    Breakpoint(usize),
+    /// The last instruction wrote to a watched address
+    Watchpoint(usize),
+}
+
+/// An Intcode computer whose memory cells are `C`, which is `i64` by default
+pub struct Program<I, O, C = i64>
 where
-    I: Fn() -> i64,
-    O: FnMut(i64),
+    I: IntcodeInput<C>,
+    O: IntcodeOutput<C>,
+    C: Cell,
 {
-    pub memory: Vec<i64>,
+    pub memory: Vec<C>,
     instruction_pointer: usize,
-    input_fn: I,
-    output_fn: O,
+    relative_base: i64,
+    queued_input: VecDeque<C>,
+    patches: Vec<(usize, C)>,
+    last_output: Option<C>,
+    tracer: Option<Box<dyn Tracer<C>>>,
+    traced_writes: Vec<(usize, C)>,
+    breakpoints: BTreeSet<usize>,
+    watchpoints: BTreeSet<usize>,
+    watch_hit: Option<usize>,
+    steps: u64,
+    step_limit: Option<u64>,
+    overflow_policy: OverflowPolicy,
+    history: VecDeque<UndoRecord<C>>,
+    history_depth: Option<usize>,
+    undo_writes: Vec<(usize, C)>,
+    undo_input: Option<C>,
+    instructions: Arc<InstructionSet<C>>,
+    input: I,
+    output: O,
+}
+
+impl Program<(), ()> {
+    /// Creates a program without I/O, to be driven by `push_input` and
+    /// `run_until_blocked`
+    pub fn new(init: &[i64]) -> Self {
+        Program::with_channels(init, (), ())
+    }
 }
 
-impl<I, O> Program<I, O>
+impl<F, G> Program<FnInput<F>, FnOutput<G>>
 where
-    I: Fn() -> i64,
-    O: FnMut(i64),
+    F: FnMut() -> i64,
+    G: FnMut(i64),
 {
-    pub fn with_io(init: &[i64], input_fn: I, output_fn: O) -> Self {
+    /// Creates a program that calls `input_fn` for each input instruction and
+    /// `output_fn` for each output instruction
+    pub fn with_io(init: &[i64], input_fn: F, output_fn: G) -> Self {
+        Program::with_channels(init, FnInput(input_fn), FnOutput(output_fn))
+    }
+}
+
+impl<I, O, C> Program<I, O, C>
+where
+    I: IntcodeInput<C>,
+    O: IntcodeOutput<C>,
+    C: Cell,
+{
+    pub fn with_channels(init: &[C], input: I, output: O) -> Self {
         Program {
             memory: init.to_vec(),
             instruction_pointer: 0,
-            input_fn,
-            output_fn,
+            relative_base: 0,
+            queued_input: VecDeque::new(),
+            patches: Vec::new(),
+            last_output: None,
+            tracer: None,
+            traced_writes: Vec::new(),
+            breakpoints: BTreeSet::new(),
+            watchpoints: BTreeSet::new(),
+            watch_hit: None,
+            steps: 0,
+            step_limit: None,
+            overflow_policy: OverflowPolicy::default(),
+            history: VecDeque::new(),
+            history_depth: None,
+            undo_writes: Vec::new(),
+            undo_input: None,
+            instructions: InstructionSet::shared_standard(),
+            input,
+            output,
+        }
+    }
+
+    /// Overwrites memory cells, given as `(address, value)` pairs. Nothing is
+    /// written unless every address is within the program's memory.
+    pub fn patch(&mut self, patches: &[(usize, C)]) -> Result<(), Error> {
+        let len = self.memory.len();
+        if let Some((address, _)) = patches.iter().find(|(address, _)| *address >= len) {
+            return Err(instruction::ErrorKind::PatchOutOfRange(*address).into());
+        }
+
+        for (address, value) in patches {
+            self.memory[*address] = value.clone();
+        }
+        self.patches.extend_from_slice(patches);
+        Ok(())
+    }
+
+    /// Like `patch`, but takes and returns the program so it can follow a constructor
+    pub fn with_patches(mut self, patches: &[(usize, C)]) -> Result<Self, Error> {
+        self.patch(patches)?;
+        Ok(self)
+    }
+
+    /// Every patch applied so far, in order
+    pub fn patches(&self) -> &[(usize, C)] {
+        &self.patches
+    }
+
+    /// Captures the program's execution state, to be resumed later with `restore`
+    pub fn snapshot(&self) -> Snapshot<C> {
+        Snapshot {
+            memory: self.memory.clone(),
+            instruction_pointer: self.instruction_pointer,
+            relative_base: self.relative_base,
+            queued_input: self.queued_input.iter().cloned().collect(),
+            steps: self.steps,
+            patches: self.patches.clone(),
+        }
+    }
+
+    /// Replaces the program's execution state with a snapshot, keeping its I/O,
+    /// tracer, breakpoints, watchpoints and step limit
+    pub fn restore(&mut self, snapshot: &Snapshot<C>) -> Result<(), snapshot::Error> {
+        if snapshot.memory.len() > MAX_MEMORY_LEN {
+            return Err(snapshot::Error::MemoryTooLarge(snapshot.memory.len()));
         }
+        if snapshot.instruction_pointer > snapshot.memory.len() {
+            return Err(snapshot::Error::InstructionPointerOutOfRange(
+                snapshot.instruction_pointer,
+                snapshot.memory.len(),
+            ));
+        }
+
+        self.memory = snapshot.memory.clone();
+        self.instruction_pointer = snapshot.instruction_pointer;
+        self.relative_base = snapshot.relative_base;
+        self.queued_input = snapshot.queued_input.iter().cloned().collect();
+        self.last_output = None;
+        self.steps = snapshot.steps;
+        self.patches = snapshot.patches.clone();
+        self.watch_hit = None;
+        self.history.clear();
+        Ok(())
+    }
+
+    /// Queues a value to be read by the next input instruction, ahead of `input`
+    pub fn push_input(&mut self, value: C) {
+        self.queued_input.push_back(value);
+    }
+
+    pub fn input(&self) -> &I {
+        &self.input
+    }
+
+    pub fn output(&self) -> &O {
+        &self.output
+    }
+
+    /// Consumes the program, returning its input and output
+    pub fn into_channels(self) -> (I, O) {
+        (self.input, self.output)
     }
 
-    pub fn run(&mut self) -> Result<(), Error> {
-        if self.memory.is_empty() {
-            return Ok(());
+    /// Whether the next instruction reads input and no value has been queued for it
+    pub fn needs_input(&self) -> bool {
+        if self.instruction_pointer == self.memory.len() {
+            return false;
         }
 
+        match self
+            .opcode_at(self.instruction_pointer)
+            .and_then(|value| self.instructions.decode(value))
+        {
+            Ok(decoded) => decoded.definition.flow == Flow::Input && self.queued_input.is_empty(),
+            Err(_) => false,
+        }
+    }
+
+    /// Replaces the instructions the program can execute, which are the
+    /// standard ones by default
+    pub fn set_instruction_set(&mut self, instructions: impl Into<Arc<InstructionSet<C>>>) {
+        self.instructions = instructions.into();
+    }
+
+    pub fn instruction_set(&self) -> &InstructionSet<C> {
+        &self.instructions
+    }
+
+    /// Calls `tracer` after every instruction the program executes
+    pub fn set_tracer(&mut self, tracer: impl Tracer<C> + 'static) {
+        self.tracer = Some(Box::new(tracer));
+    }
+
+    pub fn clear_tracer(&mut self) {
+        self.tracer = None;
+    }
+
+    /// Fails with `StepLimitExceeded` rather than execute more than `limit`
+    /// instructions in total, so a program that never halts can't hang the caller.
+    /// Instructions that halt don't count towards the limit.
+    pub fn set_step_limit(&mut self, limit: Option<u64>) {
+        self.step_limit = limit;
+    }
+
+    /// Chooses how `ADD`, `MUL` and `ARB` handle results that overflow a cell.
+    /// The default is `OverflowPolicy::Checked`.
+    pub fn set_overflow_policy(&mut self, policy: OverflowPolicy) {
+        self.overflow_policy = policy;
+    }
+
+    /// The number of instructions executed so far, not counting `HLT`
+    pub fn steps(&self) -> u64 {
+        self.steps
+    }
+
+    /// Records the effects of up to `depth` of the most recently executed
+    /// instructions so they can be undone with `step_back`. `None` stops
+    /// recording and discards the history.
+    pub fn set_history(&mut self, depth: Option<usize>) {
+        self.history_depth = depth;
+        match depth {
+            Some(depth) => {
+                while self.history.len() > depth {
+                    self.history.pop_front();
+                }
+            }
+            None => self.history.clear(),
+        }
+    }
+
+    /// Undoes the most recently recorded instruction, returning its address, or
+    /// `None` if there's no history left. Input it read is queued again, but
+    /// output it produced can't be taken back.
+    pub fn step_back(&mut self) -> Option<usize> {
+        let record = self.history.pop_back()?;
+        for (address, value) in record.writes.into_iter().rev() {
+            self.memory[address] = value;
+        }
+        self.memory.truncate(record.memory_len);
+        self.instruction_pointer = record.address;
+        self.relative_base = record.relative_base;
+        if let Some(value) = record.input {
+            self.queued_input.push_front(value);
+        }
+        self.steps -= 1;
+        self.last_output = None;
+        self.watch_hit = None;
+        Some(record.address)
+    }
+
+    /// The address of the most recent recorded instruction that wrote to `address`
+    pub fn last_writer(&self, address: usize) -> Option<usize> {
+        self.history
+            .iter()
+            .rev()
+            .find(|record| record.writes.iter().any(|(a, _)| *a == address))
+            .map(|record| record.address)
+    }
+
+    /// Makes `run` stop before executing the instruction at `address`
+    pub fn add_breakpoint(&mut self, address: usize) {
+        self.breakpoints.insert(address);
+    }
+
+    pub fn remove_breakpoint(&mut self, address: usize) -> bool {
+        self.breakpoints.remove(&address)
+    }
+
+    pub fn breakpoints(&self) -> impl Iterator<Item = &usize> {
+        self.breakpoints.iter()
+    }
+
+    /// Makes `run` and `step` stop after an instruction writes to `address`
+    pub fn add_watchpoint(&mut self, address: usize) {
+        self.watchpoints.insert(address);
+    }
+
+    pub fn remove_watchpoint(&mut self, address: usize) -> bool {
+        self.watchpoints.remove(&address)
+    }
+
+    pub fn watchpoints(&self) -> impl Iterator<Item = &usize> {
+        self.watchpoints.iter()
+    }
+
+    /// Runs until the program halts, needs input that isn't available, or
+    /// reaches a breakpoint or watchpoint. A breakpoint on the first instruction
+    /// is ignored so that calling `run` again resumes execution.
+    pub fn run(&mut self) -> Result<Stopped, Error> {
+        let mut first = true;
         loop {
-            let opcode = Opcode::parse(self.memory[self.instruction_pointer])
-                .address(self.instruction_pointer)?;
-
-            {
-                let advance_address = match opcode {
-                    Opcode::Addition { param1, param2 } => {
-                        instruction::add(self, &[param1, param2])
-                            .address(self.instruction_pointer)?
-                    }
-                    Opcode::Equals { param1, param2 } => {
-                        instruction::equals(self, &[param1, param2])?
-                    }
-                    Opcode::Halt => return Ok(()),
-                    Opcode::JumpIf {
-                        cmp: false,
-                        param1,
-                        param2,
-                    } => instruction::jump_if(false, self, &[param1, param2])
-                        .address(self.instruction_pointer)?,
-                    Opcode::JumpIf {
-                        cmp: true,
-                        param1,
-                        param2,
-                    } => instruction::jump_if(true, self, &[param1, param2])
-                        .address(self.instruction_pointer)?,
-                    Opcode::LessThan { param1, param2 } => {
-                        instruction::less_than(self, &[param1, param2])
-                            .address(self.instruction_pointer)?
-                    }
-                    Opcode::Multiplication { param1, param2 } => {
-                        instruction::multiply(self, &[param1, param2])
-                            .address(self.instruction_pointer)?
-                    }
-                    Opcode::Print { param } => {
-                        instruction::print(self, param).address(self.instruction_pointer)?
-                    }
-                    Opcode::Store => instruction::store(self).address(self.instruction_pointer)?,
-                };
+            if !first && self.breakpoints.contains(&self.instruction_pointer) {
+                return Ok(Stopped::Breakpoint(self.instruction_pointer));
+            }
+            first = false;
 
-                self.instruction_pointer = advance_address;
+            if let Some(stopped) = self.step()? {
+                return Ok(stopped);
             }
+        }
+    }
+
+    /// Executes exactly one instruction, returning `Some` if the program has
+    /// halted, can't execute an input instruction for lack of input, or the
+    /// instruction hit a watchpoint
+    pub fn step(&mut self) -> Result<Option<Stopped>, Error> {
+        self.watch_hit = None;
+        if self.instruction_pointer == self.memory.len() {
+            return Ok(Some(Stopped::Halted));
+        }
+
+        match self.execute() {
+            Ok(true) => Ok(Some(Stopped::Halted)),
+            Ok(false) => Ok(self.watch_hit.take().map(Stopped::Watchpoint)),
+            Err(e) if e.kind == instruction::ErrorKind::InputUnavailable => {
+                Ok(Some(Stopped::NeedsInput))
+            }
+            Err(e) => Err(e),
+        }
+    }
 
-            assert!(self.instruction_pointer <= self.memory.len());
+    /// Runs until the program halts, produces a value, or reaches an input
+    /// instruction when no value is queued and `input` has none available. Call
+    /// it again to resume from where it left off.
+    pub fn run_until_blocked(&mut self) -> Result<RunState<C>, Error> {
+        loop {
             if self.instruction_pointer == self.memory.len() {
-                return Ok(());
+                return Ok(RunState::Halted);
+            }
+
+            match self.execute() {
+                Ok(true) => return Ok(RunState::Halted),
+                Ok(false) => {
+                    if let Some(value) = self.last_output.take() {
+                        return Ok(RunState::Output(value));
+                    }
+                }
+                Err(e) if e.kind == instruction::ErrorKind::InputUnavailable => {
+                    return Ok(RunState::NeedsInput)
+                }
+                Err(e) => return Err(e),
             }
         }
     }
+
+    /// Decodes and executes the next instruction, returning whether it halted
+    fn execute(&mut self) -> Result<bool, Error> {
+        // Only an output from this instruction is reported by `run_until_blocked`
+        self.last_output = None;
+        let address = self.instruction_pointer;
+        let instructions = Arc::clone(&self.instructions);
+        let decoded = self
+            .opcode_at(address)
+            .and_then(|value| instructions.decode(value))
+            .map_err(|kind| self.fault(kind, address))?;
+
+        if self.tracer.is_none() {
+            return self.dispatch(&decoded);
+        }
+
+        let parameters = decoded.parameters();
+        let params: Vec<C> = (1..decoded.size())
+            .map(|offset| self.read_memory(address + offset).unwrap_or_default())
+            .collect();
+        let operands = instruction::read_operands(self, &parameters).unwrap_or_default();
+        self.traced_writes.clear();
+
+        let halted = self.dispatch(&decoded)?;
+
+        if let Some(tracer) = self.tracer.as_mut() {
+            tracer.trace(&TraceEvent {
+                address,
+                mnemonic: decoded.definition.mnemonic,
+                parameters: &parameters,
+                params: &params,
+                operands: &operands,
+                writes: &self.traced_writes,
+            });
+        }
+        Ok(halted)
+    }
+
+    /// The value of the cell at `address` as an opcode, which must fit in `i64`
+    fn opcode_at(&self, address: usize) -> Result<i64, instruction::ErrorKind> {
+        self.memory[address]
+            .to_i64()
+            .ok_or(instruction::ErrorKind::InvalidOpcode)
+    }
+
+    fn dispatch(&mut self, decoded: &Decoded<C>) -> Result<bool, Error> {
+        let address = self.instruction_pointer;
+        if decoded.definition.flow != Flow::Halt
+            && self.step_limit.is_some_and(|limit| self.steps >= limit)
+        {
+            return Err(self.fault(instruction::ErrorKind::StepLimitExceeded, address));
+        }
+
+        let relative_base = self.relative_base;
+        let memory_len = self.memory.len();
+        self.undo_writes.clear();
+        self.undo_input = None;
+
+        let advance_address = match (decoded.definition.execute)(self, &decoded.modes)
+            .map_err(|kind| self.fault(kind, address))?
+        {
+            Some(advance_address) => advance_address,
+            None => return Ok(true),
+        };
+
+        self.write_instruction_pointer(advance_address)
+            .map_err(|kind| self.fault(kind, address))?;
+        self.steps += 1;
+
+        if let Some(depth) = self.history_depth.filter(|depth| *depth > 0) {
+            if self.history.len() == depth {
+                self.history.pop_front();
+            }
+            self.history.push_back(UndoRecord {
+                address,
+                relative_base,
+                memory_len,
+                writes: std::mem::take(&mut self.undo_writes),
+                input: self.undo_input.take(),
+            });
+        }
+        Ok(false)
+    }
+
+    /// Builds an error for the instruction at `address`, with as much context
+    /// as can be decoded
+    fn fault(&self, kind: instruction::ErrorKind, address: usize) -> Error {
+        let opcode = self.read_memory(address).unwrap_or_default();
+        let mut context = Context {
+            opcode: opcode.to_i64(),
+            ..Context::default()
+        };
+
+        let decoded = opcode
+            .to_i64()
+            .ok_or(instruction::ErrorKind::InvalidOpcode)
+            .and_then(|value| self.instructions.decode(value));
+        match decoded {
+            Ok(decoded) => {
+                let parameters = decoded.parameters();
+                let params: Vec<C> = (1..decoded.size())
+                    .map(|offset| self.read_memory(address + offset).unwrap_or_default())
+                    .collect();
+                context.disassembly = Some(format!(
+                    "{:04}: {}",
+                    address,
+                    disassembler::format_parts(decoded.definition.mnemonic, &parameters, &params)
+                ));
+                context.modes = decoded.modes;
+                context.operand = match kind {
+                    instruction::ErrorKind::AddressOutOfRange(_)
+                    | instruction::ErrorKind::NegativeAddress(_) => {
+                        instruction::faulting_operand(self, &parameters)
+                    }
+                    _ => None,
+                };
+            }
+            Err(_) => context.disassembly = Some(format!("{:04}: DATA {}", address, opcode)),
+        }
+        if let instruction::ErrorKind::InvalidParameterMode(which) = kind {
+            context.operand = Some(which);
+        }
+
+        Error {
+            context: Some(Box::new(context)),
+            ..Error::new(kind, address)
+        }
+    }
 }
 
 #[macro_export]
@@ -88,69 +521,159 @@ macro_rules! new_program {
     };
 }
 
-pub trait System {
+/// Memory grows on demand up to this many cells; addresses beyond it are rejected
+pub const MAX_MEMORY_LEN: usize = 1 << 24;
+
+/// The machine state instructions operate on, with memory cells of type `C`
+pub trait System<C = i64> {
     fn get_memory_len(&self) -> usize;
-    fn read_memory(&self, address: usize) -> i64;
-    fn write_memory(&mut self, address: usize, value: i64);
+    /// Reads the cell at `address`, which is zero if memory hasn't grown to it yet
+    fn read_memory(&self, address: usize) -> Result<C, instruction::ErrorKind>;
+    /// Writes the cell at `address`, growing memory to reach it if necessary
+    fn write_memory(&mut self, address: usize, value: C) -> Result<(), instruction::ErrorKind>;
     fn read_instruction_pointer(&self) -> usize;
-    fn write_instruction_pointer(&mut self, address: usize);
-    fn read_input(&self) -> i64;
-    fn write_output(&mut self, value: i64);
+    /// Moves the instruction pointer, which may point just past the end of
+    /// memory to halt the program
+    fn write_instruction_pointer(&mut self, address: usize) -> Result<(), instruction::ErrorKind>;
+    fn read_relative_base(&self) -> i64;
+    fn write_relative_base(&mut self, base: i64);
+    fn read_input(&mut self) -> Option<C>;
+    fn write_output(&mut self, value: C);
+    /// How arithmetic instructions handle results that overflow a cell
+    fn overflow_policy(&self) -> OverflowPolicy {
+        OverflowPolicy::default()
+    }
 }
 
-impl<I, O> System for Program<I, O>
+impl<I, O, C> System<C> for Program<I, O, C>
 where
-    I: Fn() -> i64,
-    O: FnMut(i64),
+    I: IntcodeInput<C>,
+    O: IntcodeOutput<C>,
+    C: Cell,
 {
     fn get_memory_len(&self) -> usize {
         self.memory.len()
     }
 
-    fn read_memory(&self, address: usize) -> i64 {
-        self.memory[address]
+    fn read_memory(&self, address: usize) -> Result<C, instruction::ErrorKind> {
+        if address >= MAX_MEMORY_LEN {
+            return Err(instruction::ErrorKind::AddressOutOfRange(address.into()));
+        }
+        Ok(self.memory.get(address).cloned().unwrap_or_default())
     }
 
-    fn write_memory(&mut self, address: usize, value: i64) {
+    fn write_memory(&mut self, address: usize, value: C) -> Result<(), instruction::ErrorKind> {
+        if address >= MAX_MEMORY_LEN {
+            return Err(instruction::ErrorKind::AddressOutOfRange(address.into()));
+        }
+        if self.tracer.is_some() {
+            self.traced_writes.push((address, value.clone()));
+        }
+        if self.watchpoints.contains(&address) {
+            self.watch_hit = Some(address);
+        }
+        if self.history_depth.is_some() {
+            self.undo_writes.push((
+                address,
+                self.memory.get(address).cloned().unwrap_or_default(),
+            ));
+        }
+        if address >= self.memory.len() {
+            self.memory.resize(address + 1, C::zero());
+        }
         self.memory[address] = value;
+        Ok(())
     }
 
     fn read_instruction_pointer(&self) -> usize {
         self.instruction_pointer
     }
 
-    fn write_instruction_pointer(&mut self, address: usize) {
-        let _ = self.memory[address]; // panics if address is out of range
+    fn write_instruction_pointer(&mut self, address: usize) -> Result<(), instruction::ErrorKind> {
+        if address > self.memory.len() {
+            return Err(instruction::ErrorKind::JumpOutOfRange(address.into()));
+        }
         self.instruction_pointer = address;
+        Ok(())
+    }
+
+    fn read_relative_base(&self) -> i64 {
+        self.relative_base
+    }
+
+    fn write_relative_base(&mut self, base: i64) {
+        self.relative_base = base;
+    }
+
+    fn read_input(&mut self) -> Option<C> {
+        let value = match self.queued_input.pop_front() {
+            Some(value) => Some(value),
+            None => self.input.read_input(),
+        };
+        if self.history_depth.is_some() {
+            self.undo_input = value.clone();
+        }
+        value
     }
 
-    fn read_input(&self) -> i64 {
-        (self.input_fn)()
+    fn write_output(&mut self, value: C) {
+        self.last_output = Some(value.clone());
+        self.output.write_output(value);
     }
 
-    fn write_output(&mut self, value: i64) {
-        (self.output_fn)(value);
+    fn overflow_policy(&self) -> OverflowPolicy {
+        self.overflow_policy
     }
 }
 
-#[derive(Clone, Copy, Debug, Eq, Fail, PartialEq)]
-#[fail(
-    display = "Encountered an error at address {:?} while running the program",
-    address
-)]
+/// A runtime error, with as much context about the faulting instruction as
+/// could be decoded
+#[derive(Clone, Debug, Eq, PartialEq)]
 pub struct Error {
-    #[cause]
     pub kind: instruction::ErrorKind,
     pub address: Option<usize>,
+    /// Boxed so that results that might hold an error stay small
+    pub context: Option<Box<Context>>,
+}
+
+/// What could be decoded of the instruction at fault
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct Context {
+    /// The value of the instruction's first cell
+    pub opcode: Option<i64>,
+    /// The instruction's parameter modes, if its opcode could be decoded
+    pub modes: Vec<ParameterMode>,
+    /// The 1-based index of the parameter at fault, if there is one
+    pub operand: Option<usize>,
+    /// The instruction disassembled, e.g. `0004: ADD [15], #3 -> [20]`
+    pub disassembly: Option<String>,
 }
 
 impl Error {
     pub fn new(kind: instruction::ErrorKind, address: usize) -> Self {
         Error {
-            kind,
             address: Some(address),
+            ..Error::from(kind)
         }
     }
+
+    pub fn opcode(&self) -> Option<i64> {
+        self.context.as_ref().and_then(|context| context.opcode)
+    }
+
+    pub fn modes(&self) -> &[ParameterMode] {
+        self.context.as_ref().map_or(&[], |context| &context.modes)
+    }
+
+    pub fn operand(&self) -> Option<usize> {
+        self.context.as_ref().and_then(|context| context.operand)
+    }
+
+    pub fn disassembly(&self) -> Option<&str> {
+        self.context
+            .as_ref()
+            .and_then(|context| context.disassembly.as_deref())
+    }
 }
 
 impl From<instruction::ErrorKind> for Error {
@@ -158,10 +681,32 @@ impl From<instruction::ErrorKind> for Error {
         Error {
             kind,
             address: None,
+            context: None,
         }
     }
 }
 
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.kind)?;
+        match (self.disassembly(), self.address) {
+            (Some(disassembly), _) => write!(f, ", at {}", disassembly)?,
+            (None, Some(address)) => write!(f, ", at {:04}", address)?,
+            (None, None) => {}
+        }
+        if let Some(operand) = self.operand() {
+            write!(f, " (parameter {})", operand)?;
+        }
+        Ok(())
+    }
+}
+
+impl Fail for Error {
+    fn cause(&self) -> Option<&dyn Fail> {
+        Some(&self.kind)
+    }
+}
+
 pub trait ResultExt<T> {
     fn address(self, address: usize) -> Result<T, Error>;
 }
@@ -180,7 +725,10 @@ impl<T> ResultExt<T> for Result<T, instruction::ErrorKind> {
 
 #[cfg(test)]
 mod tests {
+    use super::super::{io::IterInput, trace::TextTracer};
     use super::*;
+    use num::BigInt;
+    use std::{cell::RefCell, io, rc::Rc, sync::mpsc, thread};
 
     #[test]
     fn can_initialize_program_with_memory() {
@@ -201,15 +749,80 @@ mod tests {
     fn fails_to_run_program_with_invalid_opcode() {
         let memory = [1, 5, 6, 7, 5555, 3, 7, 0];
         let mut program = new_program!(&memory);
-        let result = program.run();
+        let error = program.run().unwrap_err();
+        assert_eq!(instruction::ErrorKind::InvalidOpcode, error.kind);
+        assert_eq!(Some(4), error.address);
+        assert_eq!(Some(5555), error.opcode());
+        assert_eq!(Some("0004: DATA 5555"), error.disassembly());
+        assert_eq!(None, error.operand());
+    }
+
+    #[test]
+    fn fails_to_run_program_with_invalid_parameter_mode() {
+        let memory = [1, 0, 0, 0, 11103, 0, 99];
+        let mut program = new_program!(&memory);
+        let error = program.run().unwrap_err();
+        assert_eq!(instruction::ErrorKind::InvalidParameterMode(1), error.kind);
+        assert_eq!(Some(4), error.address);
+        assert_eq!(Some(1), error.operand());
+        assert!(error.modes().is_empty());
+    }
+
+    #[test]
+    fn errors_from_every_instruction_carry_their_address() {
+        for (opcode, params) in &[
+            (1, 3),
+            (2, 3),
+            (3, 1),
+            (4, 1),
+            (5, 2),
+            (6, 2),
+            (7, 3),
+            (8, 3),
+            (9, 1),
+        ] {
+            let mut memory = vec![1101, 0, 0, 20, *opcode];
+            memory.extend(std::iter::repeat_n(-1, *params));
+            memory.push(99);
+            let mut program = Program::with_io(&memory, || 0, |_| ());
+            let error = program.run().unwrap_err();
+            assert_eq!(
+                (
+                    instruction::ErrorKind::NegativeAddress(instruction::Value::Exact(-1)),
+                    Some(4),
+                    Some(1)
+                ),
+                (error.kind, error.address, error.operand()),
+                "opcode {}",
+                opcode
+            );
+        }
+    }
+
+    #[test]
+    fn displays_errors_with_the_faulting_instruction() {
+        let memory = [1, 0, 0, 0, 21107, 1, 2, -7, 99];
+        let mut program = new_program!(&memory);
+        let error = program.run().unwrap_err();
+        assert_eq!(Some(21107), error.opcode());
+        assert_eq!(
+            vec![
+                ParameterMode::Immediate,
+                ParameterMode::Immediate,
+                ParameterMode::Relative
+            ],
+            error.modes()
+        );
         assert_eq!(
-            Err(Error::new(instruction::ErrorKind::InvalidOpcode, 4)),
-            result
+            "Parameter refers to negative address -7, at 0004: LT #1, #2 -> [rb-7] (parameter 3)",
+            error.to_string()
+        );
+        assert_eq!(
+            "Program exceeded its limit on executed instructions, at 0012",
+            Error::new(instruction::ErrorKind::StepLimitExceeded, 12).to_string()
         );
     }
 
-    // TODO: test that Errors are enriched with the right instruction pointer address
-
     #[test]
     fn adds_when_both_parameters_are_in_position_mode() {
         let memory = [1, 5, 6, 0, 99, 10, 20];
@@ -347,6 +960,140 @@ mod tests {
         assert!(result.is_ok());
     }
 
+    #[test]
+    fn jumping_to_a_negative_address_fails() {
+        let memory = [1105, 1, -2, 99];
+        let mut program = new_program!(&memory);
+        let error = program.run().unwrap_err();
+        assert_eq!(
+            instruction::ErrorKind::NegativeJump(instruction::Value::Exact(-2)),
+            error.kind
+        );
+        assert_eq!(Some(0), error.address);
+        assert_eq!(0, program.read_instruction_pointer());
+    }
+
+    #[test]
+    fn jumping_beyond_the_end_of_memory_fails() {
+        let memory = [1106, 0, 9, 99];
+        let mut program = new_program!(&memory);
+        let error = program.run().unwrap_err();
+        assert_eq!(
+            instruction::ErrorKind::JumpOutOfRange(instruction::Value::Exact(9)),
+            error.kind
+        );
+        assert_eq!(Some(0), error.address);
+    }
+
+    #[test]
+    fn arithmetic_overflow_fails_by_default() {
+        let memory = [1102, 9_223_372_036_854_775_807, 2, 0, 99];
+        let mut program = new_program!(&memory);
+        let error = program.run().unwrap_err();
+        assert_eq!(instruction::ErrorKind::ArithmeticOverflow, error.kind);
+        assert_eq!(Some(0), error.address);
+        assert_eq!(&memory, &program.memory[..]);
+    }
+
+    #[test]
+    fn arithmetic_follows_the_overflow_policy() {
+        let memory = [1101, 9_223_372_036_854_775_807, 1, 0, 99];
+        for (policy, expected) in &[
+            (OverflowPolicy::Wrapping, i64::MIN),
+            (OverflowPolicy::Saturating, i64::MAX),
+        ] {
+            let mut program = new_program!(&memory);
+            program.set_overflow_policy(*policy);
+            assert_eq!(Ok(Stopped::Halted), program.run());
+            assert_eq!(*expected, program.memory[0]);
+        }
+    }
+
+    #[test]
+    fn relative_base_adjustment_follows_the_overflow_policy() {
+        let memory = [109, 9_223_372_036_854_775_807, 109, 1, 99];
+        let mut program = new_program!(&memory);
+        assert_eq!(
+            instruction::ErrorKind::ArithmeticOverflow,
+            program.run().unwrap_err().kind
+        );
+
+        let mut program = new_program!(&memory);
+        program.set_overflow_policy(OverflowPolicy::Saturating);
+        assert_eq!(Ok(Stopped::Halted), program.run());
+        assert_eq!(i64::MAX, program.read_relative_base());
+    }
+
+    #[test]
+    fn runs_with_i128_cells() {
+        let memory: [i128; 8] = [1102, 1 << 62, 4, 7, 4, 7, 99, 0];
+        let mut output = Vec::new();
+        let mut program = Program::with_channels(&memory, (), &mut output);
+        assert_eq!(Ok(Stopped::Halted), program.run());
+        assert_eq!(vec![1 << 64], output);
+    }
+
+    #[test]
+    fn overflows_at_the_width_of_i32_cells() {
+        let memory: [i32; 5] = [1102, i32::MAX, 2, 0, 99];
+        let mut program = Program::with_channels(&memory, (), ());
+        let error = program.run().unwrap_err();
+        assert_eq!(instruction::ErrorKind::ArithmeticOverflow, error.kind);
+        assert_eq!(Some(1102), error.opcode());
+
+        let mut program = Program::with_channels(&memory, (), ());
+        program.set_overflow_policy(OverflowPolicy::Wrapping);
+        assert_eq!(Ok(Stopped::Halted), program.run());
+        assert_eq!(-2, program.memory[0]);
+    }
+
+    #[test]
+    fn runs_with_big_integer_cells() {
+        let memory: Vec<BigInt> = [109, 10, 22202, 0, 0, 1, 204, 1, 99, 0, i64::MAX]
+            .iter()
+            .map(|value| BigInt::from(*value))
+            .collect();
+        let mut program = Program::with_channels(&memory, (), ());
+        assert_eq!(
+            Ok(RunState::Output(
+                BigInt::from(i64::MAX) * BigInt::from(i64::MAX)
+            )),
+            program.run_until_blocked()
+        );
+        assert_eq!(Ok(RunState::Halted), program.run_until_blocked());
+    }
+
+    #[test]
+    fn reports_addresses_beyond_i64_exactly() {
+        let address: i128 = 1 << 70;
+        let mut program = Program::with_channels(&[4, address, 99], (), ());
+        assert_eq!(
+            instruction::ErrorKind::AddressOutOfRange(instruction::Value::Exact(address)),
+            program.run().unwrap_err().kind
+        );
+
+        let mut program = Program::with_channels(&[1105, 1, -address], (), ());
+        assert_eq!(
+            instruction::ErrorKind::NegativeJump(instruction::Value::Exact(-address)),
+            program.run().unwrap_err().kind
+        );
+
+        let memory = [BigInt::from(4), BigInt::from(1) << 200, BigInt::from(99)];
+        let mut program = Program::with_channels(&memory, (), ());
+        let error = program.run().unwrap_err();
+        assert_eq!(
+            "Parameter refers to address at least 2^200, beyond the memory limit",
+            error.kind.to_string()
+        );
+    }
+
+    #[test]
+    fn jumping_to_the_end_of_memory_halts() {
+        let memory = [1106, 0, 3];
+        let mut program = new_program!(&memory);
+        assert_eq!(Ok(Stopped::Halted), program.run());
+    }
+
     #[test]
     fn less_than_is_true_when_1st_position_mode_param_is_less_than_2nd_position_mode_param() {
         let memory = [7, 5, 6, 7, 99, 1, 2, -1];
@@ -532,6 +1279,546 @@ mod tests {
         assert_eq!(&[3, 3, 99, 77], &program.memory[..]);
     }
 
+    #[test]
+    fn stores_input_value_in_relative_mode() {
+        let memory = [109, 3, 203, 2, 99, 0];
+        let mut program = Program::with_io(&memory, || 77, |_| unreachable!());
+        assert!(program.run().is_ok());
+        assert_eq!(&[109, 3, 203, 2, 99, 77], &program.memory[..]);
+    }
+
+    #[test]
+    fn adjusts_relative_base_in_immediate_mode() {
+        let memory = [109, 19, 99];
+        let mut program = new_program!(&memory);
+        assert!(program.run().is_ok());
+        assert_eq!(19, program.read_relative_base());
+    }
+
+    #[test]
+    fn adjusts_relative_base_cumulatively_in_position_and_relative_modes() {
+        let memory = [9, 5, 209, 1, 99, 3];
+        let mut program = new_program!(&memory);
+        assert!(program.run().is_ok());
+        assert_eq!(102, program.read_relative_base());
+    }
+
+    #[test]
+    fn adds_when_parameters_are_in_relative_mode() {
+        let memory = [109, 7, 22201, 0, 1, 2, 99, 10, 20, -1];
+        let mut program = new_program!(&memory);
+        assert!(program.run().is_ok());
+        assert_eq!(
+            &[109, 7, 22201, 0, 1, 2, 99, 10, 20, 30],
+            &program.memory[..]
+        );
+    }
+
+    #[test]
+    fn prints_when_parameter_is_in_relative_mode() {
+        let memory = [109, 5, 204, 0, 99, 77];
+        let mut program = Program::with_io(&memory, || unreachable!(), |i| assert_eq!(77, i));
+        assert!(program.run().is_ok());
+    }
+
+    #[test]
+    fn fails_to_run_program_with_relative_address_out_of_range() {
+        let memory = [109, -10, 204, 1, 99];
+        let mut program = new_program!(&memory);
+        let error = program.run().unwrap_err();
+        assert_eq!(
+            instruction::ErrorKind::NegativeAddress(instruction::Value::Exact(-9)),
+            error.kind
+        );
+        assert_eq!(Some(2), error.address);
+        assert_eq!(Some(1), error.operand());
+        assert_eq!(Some("0002: OUT [rb+1]"), error.disassembly());
+    }
+
+    #[test]
+    fn reads_and_writes_memory_beyond_the_image() {
+        let memory = [1001, 10, 5, 12, 99];
+        let mut program = new_program!(&memory);
+        assert!(program.run().is_ok());
+        assert_eq!(
+            &[1001, 10, 5, 12, 99, 0, 0, 0, 0, 0, 0, 0, 5],
+            &program.memory[..]
+        );
+    }
+
+    #[test]
+    fn fails_to_run_program_with_address_beyond_memory_limit() {
+        let memory = [1101, 1, 2, MAX_MEMORY_LEN as i64, 99];
+        let mut program = new_program!(&memory);
+        let error = program.run().unwrap_err();
+        assert_eq!(
+            instruction::ErrorKind::AddressOutOfRange(MAX_MEMORY_LEN.into()),
+            error.kind
+        );
+        assert_eq!(Some(0), error.address);
+        assert_eq!(Some(3), error.operand());
+    }
+
+    #[test]
+    fn runs_program_that_outputs_a_copy_of_itself() {
+        let mut output = Vec::<i64>::new();
+        let memory = [
+            109, 1, 204, -1, 1001, 100, 1, 100, 1008, 100, 16, 101, 1006, 101, 0, 99,
+        ];
+        let mut program = Program::with_io(&memory, || unreachable!(), |i| output.push(i));
+        assert!(program.run().is_ok());
+        assert_eq!(&memory, &output[..]);
+    }
+
+    #[test]
+    fn runs_program_that_outputs_large_numbers() {
+        let mut output = Vec::<i64>::new();
+        let memory = [1102, 34915192, 34915192, 7, 4, 7, 99, 0];
+        let mut program = Program::with_io(&memory, || unreachable!(), |i| output.push(i));
+        assert!(program.run().is_ok());
+        assert_eq!(&[1219070632396864], &output[..]);
+    }
+
+    #[test]
+    fn reads_queued_input_before_calling_input_fn() {
+        let memory = [3, 7, 3, 8, 99, 0, 0, 0, 0];
+        let mut program = Program::with_io(&memory, || 2, |_| unreachable!());
+        program.push_input(1);
+        assert!(program.run().is_ok());
+        assert_eq!(&[3, 7, 3, 8, 99, 0, 0, 1, 2], &program.memory[..]);
+    }
+
+    #[test]
+    fn runs_with_queue_input_and_vec_output() {
+        let memory = [3, 0, 4, 0, 3, 0, 4, 0, 99];
+        let mut output = Vec::new();
+        let input: VecDeque<i64> = vec![4, 5].into();
+        let mut program = Program::with_channels(&memory, input, &mut output);
+        assert_eq!(Ok(Stopped::Halted), program.run());
+        assert!(program.input().is_empty());
+        assert_eq!(vec![4, 5], output);
+    }
+
+    #[test]
+    fn run_stops_when_input_runs_out() {
+        let memory = [3, 0, 4, 0, 3, 0, 4, 0, 99];
+        let mut program = Program::with_channels(&memory, IterInput(Some(6).into_iter()), vec![]);
+        assert_eq!(Ok(Stopped::NeedsInput), program.run());
+        assert_eq!(4, program.read_instruction_pointer());
+
+        program.push_input(7);
+        assert_eq!(Ok(Stopped::Halted), program.run());
+        assert_eq!(vec![6, 7], program.into_channels().1);
+    }
+
+    #[test]
+    fn runs_on_another_thread_over_channels() {
+        let memory = [3, 11, 1002, 11, 2, 11, 4, 11, 1105, 1, 0, 0];
+        let (input_tx, input_rx) = mpsc::channel();
+        let (output_tx, output_rx) = mpsc::channel();
+        let handle =
+            thread::spawn(move || Program::with_channels(&memory, input_rx, output_tx).run());
+
+        for value in 1..=3 {
+            input_tx.send(value).unwrap();
+            assert_eq!(Ok(value * 2), output_rx.recv());
+        }
+        drop(input_tx);
+        assert_eq!(Ok(Stopped::NeedsInput), handle.join().unwrap());
+    }
+
+    #[test]
+    fn patches_memory_and_records_the_patches() {
+        let memory = [1, 0, 0, 0, 99];
+        let mut program = Program::new(&memory).with_patches(&[(1, 4)]).unwrap();
+        program.patch(&[(2, 4), (1, 3)]).unwrap();
+        assert_eq!(&[1, 3, 4, 0, 99], &program.memory[..]);
+        assert_eq!(&[(1, 4), (2, 4), (1, 3)], program.patches());
+
+        assert_eq!(Ok(Stopped::Halted), program.run());
+        assert_eq!(99, program.memory[0]);
+    }
+
+    #[test]
+    fn rejects_patches_outside_memory_without_applying_any() {
+        let memory = [1, 0, 0, 0, 99];
+        let mut program = Program::new(&memory);
+        assert_eq!(
+            Err(Error::from(instruction::ErrorKind::PatchOutOfRange(5))),
+            program.patch(&[(1, 4), (5, 0)])
+        );
+        assert_eq!(None, program.patch(&[(5, 0)]).unwrap_err().address);
+        assert_eq!(&memory, &program.memory[..]);
+        assert!(program.patches().is_empty());
+    }
+
+    #[test]
+    fn restores_a_snapshot_to_branch_execution() {
+        // Reads a value, outputs it doubled, and halts
+        let memory = [3, 9, 1002, 9, 2, 9, 4, 9, 99, 0];
+        let mut program = Program::new(&memory).with_patches(&[(5, 9)]).unwrap();
+        program.push_input(7);
+        program.push_input(8);
+        let snapshot = program.snapshot();
+
+        assert_eq!(Ok(RunState::Output(14)), program.run_until_blocked());
+        assert_eq!(Ok(RunState::Halted), program.run_until_blocked());
+
+        program.restore(&snapshot).unwrap();
+        assert_eq!(&memory[..5], &program.memory[..5]);
+        assert_eq!(0, program.steps());
+        assert_eq!(&[(5, 9)], program.patches());
+        assert_eq!(Ok(RunState::Output(14)), program.run_until_blocked());
+    }
+
+    #[test]
+    fn restores_a_snapshot_mid_run_in_another_program() {
+        let memory = [109, 5, 4, 0, 204, -3, 99];
+        let mut program = Program::new(&memory);
+        program.step().unwrap();
+        program.step().unwrap();
+        let snapshot = program.snapshot();
+
+        let mut output = Vec::new();
+        let mut other = Program::with_channels(&[], (), &mut output);
+        other
+            .restore(&Snapshot::from_bytes(&snapshot.to_bytes()).unwrap())
+            .unwrap();
+        assert_eq!(4, other.read_instruction_pointer());
+        assert_eq!(5, other.read_relative_base());
+        assert_eq!(2, other.steps());
+        assert_eq!(Ok(RunState::Output(4)), other.run_until_blocked());
+        assert_eq!(Ok(Stopped::Halted), other.run());
+        assert_eq!(vec![4], output);
+    }
+
+    #[test]
+    fn rejects_a_snapshot_with_an_invalid_instruction_pointer() {
+        let mut snapshot = Program::new(&[99]).snapshot();
+        snapshot.instruction_pointer = 2;
+
+        let mut program = Program::new(&[1, 0, 0, 0, 99]);
+        assert!(matches!(
+            program.restore(&snapshot),
+            Err(snapshot::Error::InstructionPointerOutOfRange(2, 1))
+        ));
+        assert_eq!(0, program.read_instruction_pointer());
+        assert_eq!(5, program.memory.len());
+    }
+
+    #[test]
+    fn steps_back_through_recorded_history() {
+        // Reads a value into [13], doubles it, sets the relative base, outputs
+        let memory = [3, 13, 1002, 13, 2, 13, 109, 4, 204, 9, 99, 0, 0, 0];
+        let mut program = Program::new(&memory);
+        program.set_history(Some(10));
+        program.push_input(21);
+        assert_eq!(Ok(RunState::Output(42)), program.run_until_blocked());
+        assert_eq!(4, program.steps());
+
+        assert_eq!(Some(8), program.step_back());
+        assert_eq!(Some(6), program.step_back());
+        assert_eq!(0, program.read_relative_base());
+        assert_eq!(Some(2), program.step_back());
+        assert_eq!(21, program.memory[13]);
+        assert_eq!(Some(0), program.step_back());
+        assert_eq!(None, program.step_back());
+        assert_eq!(&memory, &program.memory[..]);
+        assert_eq!(0, program.steps());
+
+        // The input that was read is queued again
+        assert_eq!(Ok(RunState::Output(42)), program.run_until_blocked());
+    }
+
+    #[test]
+    fn step_back_shrinks_memory_that_grew() {
+        let memory = [1101, 1, 2, 10, 99];
+        let mut program = Program::new(&memory);
+        program.set_history(Some(1));
+        program.step().unwrap();
+        assert_eq!(11, program.memory.len());
+        assert_eq!(Some(0), program.step_back());
+        assert_eq!(&memory, &program.memory[..]);
+    }
+
+    #[test]
+    fn history_keeps_only_the_most_recent_instructions() {
+        let memory = [1101, 1, 2, 14, 1101, 3, 4, 14, 1101, 5, 6, 15, 99, 0, 0, 0];
+        let mut program = Program::new(&memory);
+        program.set_history(Some(2));
+        assert_eq!(Ok(Stopped::Halted), program.run());
+
+        assert_eq!(Some(4), program.last_writer(14));
+        assert_eq!(Some(8), program.last_writer(15));
+        assert_eq!(None, program.last_writer(0));
+
+        assert_eq!(Some(8), program.step_back());
+        assert_eq!(Some(4), program.step_back());
+        assert_eq!(None, program.step_back());
+        assert_eq!(3, program.memory[14]);
+    }
+
+    #[test]
+    fn records_no_history_by_default() {
+        let memory = [1101, 1, 2, 0, 99];
+        let mut program = Program::new(&memory);
+        assert_eq!(Ok(Stopped::Halted), program.run());
+        assert_eq!(None, program.last_writer(0));
+        assert_eq!(None, program.step_back());
+    }
+
+    #[test]
+    fn run_until_blocked_pauses_when_input_is_needed() {
+        let memory = [3, 0, 4, 0, 99];
+        let mut program = Program::new(&memory);
+        assert_eq!(Ok(RunState::NeedsInput), program.run_until_blocked());
+        assert_eq!(Ok(RunState::NeedsInput), program.run_until_blocked());
+        assert_eq!(0, program.read_instruction_pointer());
+
+        program.push_input(42);
+        assert_eq!(Ok(RunState::Output(42)), program.run_until_blocked());
+        assert_eq!(Ok(RunState::Halted), program.run_until_blocked());
+        assert_eq!(Ok(RunState::Halted), program.run_until_blocked());
+    }
+
+    #[test]
+    fn needs_input_only_when_next_instruction_reads_from_an_empty_queue() {
+        let memory = [104, 1, 3, 0, 99];
+        let mut program = Program::new(&memory);
+        assert!(!program.needs_input());
+        assert_eq!(Ok(None), program.step());
+        assert!(program.needs_input());
+        program.push_input(5);
+        assert!(!program.needs_input());
+    }
+
+    #[test]
+    fn run_until_blocked_returns_each_output_in_turn() {
+        let memory = [104, 1, 104, 2, 3, 9, 4, 9, 99, 0];
+        let mut program = Program::new(&memory);
+        assert_eq!(Ok(RunState::Output(1)), program.run_until_blocked());
+        assert_eq!(Ok(RunState::Output(2)), program.run_until_blocked());
+        assert_eq!(Ok(RunState::NeedsInput), program.run_until_blocked());
+
+        program.push_input(3);
+        assert_eq!(Ok(RunState::Output(3)), program.run_until_blocked());
+        assert_eq!(Ok(RunState::Halted), program.run_until_blocked());
+    }
+
+    #[test]
+    fn run_until_blocked_ignores_output_from_earlier_steps() {
+        let memory = [104, 5, 1101, 1, 1, 9, 99, 0, 0, 0];
+        let mut program = Program::new(&memory);
+        assert_eq!(Ok(None), program.step());
+        assert_eq!(Ok(RunState::Halted), program.run_until_blocked());
+
+        let mut program = Program::new(&[104, 5, 1101, 1, 1, 9, 104, 6, 99, 0]);
+        assert_eq!(Ok(None), program.step());
+        assert_eq!(Ok(RunState::Output(6)), program.run_until_blocked());
+        assert_eq!(Ok(RunState::Halted), program.run_until_blocked());
+
+        let mut program = Program::new(&[104, 5, 3, 5, 99, 0]);
+        assert_eq!(Ok(Stopped::NeedsInput), program.run());
+        program.push_input(1);
+        assert_eq!(Ok(RunState::Halted), program.run_until_blocked());
+    }
+
+    #[test]
+    fn run_until_blocked_halts_at_the_end_of_memory() {
+        let memory = [1101, 1, 2, 0];
+        let mut program = Program::new(&memory);
+        assert_eq!(Ok(RunState::Halted), program.run_until_blocked());
+        assert_eq!(&[3, 1, 2, 0], &program.memory[..]);
+    }
+
+    #[test]
+    fn run_until_blocked_forwards_output_to_output_fn() {
+        let mut output = Vec::<i64>::new();
+        let memory = [104, 5, 99];
+        let mut program = Program::with_io(&memory, || unreachable!(), |i| output.push(i));
+        assert_eq!(Ok(RunState::Output(5)), program.run_until_blocked());
+        assert_eq!(Ok(RunState::Halted), program.run_until_blocked());
+        drop(program);
+        assert_eq!(&[5], &output[..]);
+    }
+
+    #[derive(Clone, Default)]
+    struct SharedBuffer(Rc<RefCell<Vec<u8>>>);
+
+    impl io::Write for SharedBuffer {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            self.0.borrow_mut().write(buf)
+        }
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn traces_every_executed_instruction() {
+        let events = Rc::new(RefCell::new(Vec::new()));
+        let memory = [1001, 7, 3, 8, 4, 8, 99, 4, 0];
+        let mut program = Program::with_io(&memory, || unreachable!(), |_| ());
+        let recorded = events.clone();
+        program.set_tracer(move |event: &TraceEvent| {
+            recorded.borrow_mut().push((
+                event.address,
+                event.mnemonic.to_string(),
+                event.operands.to_vec(),
+                event.writes.to_vec(),
+            ))
+        });
+        assert!(program.run().is_ok());
+
+        assert_eq!(
+            vec![
+                (0, "ADD".to_string(), vec![4, 3], vec![(8, 7)]),
+                (4, "OUT".to_string(), vec![7], vec![]),
+                (6, "HLT".to_string(), vec![], vec![]),
+            ],
+            *events.borrow()
+        );
+    }
+
+    #[test]
+    fn text_tracer_writes_a_line_per_instruction() {
+        let buffer = SharedBuffer::default();
+        let memory = [3, 9, 1008, 9, 8, 10, 4, 10, 99, 0, 0];
+        let mut program = Program::with_io(&memory, || 8, |_| ());
+        program.set_tracer(TextTracer::new(buffer.clone()));
+        assert!(program.run().is_ok());
+
+        assert_eq!(
+            "0000: IN -> [9] ; [9] <- 8\n\
+             0002: EQ [9], #8 -> [10] ; in 8, 8 ; [10] <- 1\n\
+             0006: OUT [10] ; in 1\n\
+             0008: HLT\n",
+            String::from_utf8(buffer.0.borrow().clone()).unwrap()
+        );
+    }
+
+    #[test]
+    fn stops_tracing_when_tracer_is_cleared() {
+        let count = Rc::new(RefCell::new(0));
+        let memory = [1101, 1, 1, 0, 99];
+        let mut program = new_program!(&memory);
+        let counter = count.clone();
+        program.set_tracer(move |_: &TraceEvent| *counter.borrow_mut() += 1);
+        program.clear_tracer();
+        assert!(program.run().is_ok());
+        assert_eq!(0, *count.borrow());
+    }
+
+    #[test]
+    fn steps_one_instruction_at_a_time() {
+        let memory = [1101, 1, 2, 9, 1001, 9, 10, 9, 99];
+        let mut program = new_program!(&memory);
+        assert_eq!(Ok(None), program.step());
+        assert_eq!(4, program.read_instruction_pointer());
+        assert_eq!(3, program.memory[9]);
+
+        assert_eq!(Ok(None), program.step());
+        assert_eq!(8, program.read_instruction_pointer());
+        assert_eq!(13, program.memory[9]);
+
+        assert_eq!(Ok(Some(Stopped::Halted)), program.step());
+        assert_eq!(Ok(Some(Stopped::Halted)), program.step());
+        assert_eq!(8, program.read_instruction_pointer());
+    }
+
+    #[test]
+    fn run_stops_at_breakpoint_and_resumes() {
+        let memory = [1101, 1, 2, 11, 1001, 11, 10, 11, 104, 0, 99, 0];
+        let mut program = Program::new(&memory);
+        program.add_breakpoint(4);
+        program.add_breakpoint(10);
+
+        assert_eq!(Ok(Stopped::Breakpoint(4)), program.run());
+        assert_eq!(4, program.read_instruction_pointer());
+        assert_eq!(3, program.memory[11]);
+
+        assert_eq!(Ok(Stopped::Breakpoint(10)), program.run());
+        assert_eq!(13, program.memory[11]);
+
+        assert!(program.remove_breakpoint(10));
+        assert_eq!(Ok(Stopped::Halted), program.run());
+    }
+
+    #[test]
+    fn run_ignores_breakpoint_on_first_instruction() {
+        let memory = [1101, 1, 2, 0, 99];
+        let mut program = new_program!(&memory);
+        program.add_breakpoint(0);
+        assert_eq!(Ok(Stopped::Halted), program.run());
+        assert_eq!(vec![&0], program.breakpoints().collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn run_stops_after_write_to_watched_address() {
+        let memory = [1101, 1, 2, 14, 1101, 3, 4, 13, 1101, 5, 6, 14, 99, 0, 0];
+        let mut program = new_program!(&memory);
+        program.add_watchpoint(13);
+
+        assert_eq!(Ok(Stopped::Watchpoint(13)), program.run());
+        assert_eq!(8, program.read_instruction_pointer());
+        assert_eq!(7, program.memory[13]);
+
+        assert!(program.remove_watchpoint(13));
+        assert!(!program.remove_watchpoint(13));
+        assert_eq!(Ok(Stopped::Halted), program.run());
+        assert_eq!(11, program.memory[14]);
+    }
+
+    #[test]
+    fn step_reports_watchpoint() {
+        let memory = [1101, 1, 2, 5, 99, 0];
+        let mut program = new_program!(&memory);
+        program.add_watchpoint(5);
+        assert_eq!(Ok(Some(Stopped::Watchpoint(5))), program.step());
+        assert_eq!(Ok(Some(Stopped::Halted)), program.step());
+    }
+
+    #[test]
+    fn counts_executed_instructions() {
+        let memory = [1101, 1, 2, 9, 1001, 9, 10, 9, 99];
+        let mut program = new_program!(&memory);
+        assert_eq!(0, program.steps());
+        assert!(program.run().is_ok());
+        assert_eq!(2, program.steps());
+        assert!(program.run().is_ok());
+        assert_eq!(2, program.steps());
+    }
+
+    #[test]
+    fn fails_when_step_limit_is_exceeded() {
+        let memory = [1101, 1, 2, 9, 1105, 1, 4];
+        let mut program = new_program!(&memory);
+        program.set_step_limit(Some(100));
+        let error = program.run().unwrap_err();
+        assert_eq!(instruction::ErrorKind::StepLimitExceeded, error.kind);
+        assert_eq!(Some("0004: JT #1, #4"), error.disassembly());
+        assert_eq!(100, program.steps());
+    }
+
+    #[test]
+    fn halts_when_step_limit_is_exactly_reached() {
+        let memory = [1101, 1, 2, 9, 99];
+        let mut program = new_program!(&memory);
+        program.set_step_limit(Some(1));
+        assert_eq!(Ok(Stopped::Halted), program.run());
+    }
+
+    #[test]
+    fn resumes_after_step_limit_is_raised() {
+        let memory = [1101, 1, 2, 9, 1101, 3, 4, 10, 99];
+        let mut program = new_program!(&memory);
+        program.set_step_limit(Some(1));
+        assert!(program.run().is_err());
+        program.set_step_limit(None);
+        assert_eq!(Ok(Stopped::Halted), program.run());
+        assert_eq!(&[3, 7], &program.memory[9..]);
+    }
+
     #[test]
     fn halts_the_program() {
         let memory = [99, 1101, 10, 20, 0];
@@ -567,53 +1854,87 @@ mod tests {
     fn system_returns_memory_at_address() {
         let memory = [5, 4, 3];
         let program = new_program!(&memory);
-        assert_eq!(4, program.read_memory(1));
+        assert_eq!(Ok(4), program.read_memory(1));
     }
 
     #[test]
-    #[should_panic(expected = "index out of bounds: the len is 3 but the index is 55")]
-    fn system_panics_when_requested_memory_address_is_out_of_range() {
+    fn system_returns_zero_when_requested_memory_address_is_beyond_the_image() {
         let memory = [5, 4, 3];
         let program = new_program!(&memory);
-        program.read_memory(55);
+        assert_eq!(Ok(0), program.read_memory(55));
+        assert_eq!(memory.len(), program.get_memory_len());
     }
 
     #[test]
     fn system_writes_memory_at_address() {
         let memory = [5, 4, 3];
         let mut program = new_program!(&memory);
-        program.write_memory(1, 7);
+        assert_eq!(Ok(()), program.write_memory(1, 7));
         assert_eq!(&[5, 7, 3], &program.memory[..]);
     }
 
     #[test]
-    #[should_panic(expected = "index out of bounds: the len is 3 but the index is 4")]
-    fn system_panics_when_asked_to_write_memory_out_of_range() {
+    fn system_grows_memory_when_asked_to_write_beyond_the_image() {
         let memory = [5, 4, 3];
         let mut program = new_program!(&memory);
-        program.write_memory(4, 0);
+        assert_eq!(Ok(()), program.write_memory(5, 7));
+        assert_eq!(&[5, 4, 3, 0, 0, 7], &program.memory[..]);
     }
 
     #[test]
     fn system_updates_instruction_pointer() {
         let memory = [5, 4, 3];
         let mut program = new_program!(&memory);
-        program.write_instruction_pointer(2);
+        assert_eq!(Ok(()), program.write_instruction_pointer(2));
         assert_eq!(2, program.read_instruction_pointer());
+        assert_eq!(Ok(()), program.write_instruction_pointer(memory.len()));
+        assert_eq!(memory.len(), program.read_instruction_pointer());
+    }
+
+    #[test]
+    fn system_rejects_instruction_pointer_beyond_the_end_of_memory() {
+        let memory = [5, 4, 3];
+        let mut program = new_program!(&memory);
+        assert_eq!(
+            Err(instruction::ErrorKind::JumpOutOfRange(
+                instruction::Value::Exact(4)
+            )),
+            program.write_instruction_pointer(memory.len() + 1)
+        );
+        assert_eq!(0, program.read_instruction_pointer());
+    }
+
+    #[test]
+    fn system_rejects_memory_addresses_beyond_the_limit() {
+        let memory = [5, 4, 3];
+        let mut program = new_program!(&memory);
+        assert_eq!(
+            Err(instruction::ErrorKind::AddressOutOfRange(
+                MAX_MEMORY_LEN.into()
+            )),
+            program.read_memory(MAX_MEMORY_LEN)
+        );
+        assert_eq!(
+            Err(instruction::ErrorKind::AddressOutOfRange(
+                MAX_MEMORY_LEN.into()
+            )),
+            program.write_memory(MAX_MEMORY_LEN, 7)
+        );
+        assert_eq!(memory.len(), program.get_memory_len());
     }
 
     #[test]
-    #[should_panic(expected = "index out of bounds: the len is 3 but the index is 3")]
-    fn system_panics_if_updated_instruction_pointer_is_out_of_range() {
+    fn system_updates_relative_base() {
         let memory = [5, 4, 3];
         let mut program = new_program!(&memory);
-        program.write_instruction_pointer(memory.len());
+        program.write_relative_base(-7);
+        assert_eq!(-7, program.read_relative_base());
     }
 
     #[test]
     fn system_reads_a_value_from_input() {
-        let program = Program::with_io(&[], || 5_i64, |_| unimplemented!());
-        assert_eq!(5, program.read_input());
+        let mut program = Program::with_io(&[], || 5_i64, |_| unimplemented!());
+        assert_eq!(Some(5), program.read_input());
     }
 
     #[test]
@@ -0,0 +1,561 @@
+use super::cell::Cell;
+use super::instruction::{self, ErrorKind, ParameterMode, ParameterType};
+use super::program::System;
+use std::any::{Any, TypeId};
+use std::collections::{BTreeMap, HashMap};
+use std::sync::{Arc, Mutex, OnceLock};
+
+/// The opcode of the standard `HLT` instruction
+pub const HALT: i64 = 99;
+
+/// Executes a decoded instruction given the mode of each parameter, returning
+/// the address of the next instruction, or `None` to halt. An executor that
+/// reads input should do so before changing any state, so that the instruction
+/// can be retried if no input is available yet.
+pub type Executor<C = i64> =
+    fn(system: &mut dyn System<C>, modes: &[ParameterMode]) -> Result<Option<usize>, ErrorKind>;
+
+/// Where control can go after an instruction, for tools that look at a program
+/// without running it
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum Flow {
+    /// Continues with the next instruction
+    Next,
+    /// Reads a value of input, then continues with the next instruction
+    Input,
+    /// Jumps to the second operand if the first is nonzero (`true`) or zero
+    /// (`false`), and otherwise continues with the next instruction
+    JumpIf(bool),
+    Halt,
+    /// May continue anywhere, or halt
+    Unknown,
+}
+
+/// Describes one instruction of an instruction set
+#[derive(Clone)]
+pub struct Definition<C = i64> {
+    /// The two-digit opcode, from 1 to 99
+    pub code: i64,
+    pub mnemonic: &'static str,
+    pub parameters: Vec<ParameterType>,
+    pub flow: Flow,
+    pub execute: Executor<C>,
+}
+
+#[derive(Clone, Debug, Eq, Fail, PartialEq)]
+pub enum Error {
+    #[fail(display = "Opcode {} doesn't fit in two digits", _0)]
+    InvalidCode(i64),
+    #[fail(display = "Opcode {} is already defined", _0)]
+    DuplicateCode(i64),
+    #[fail(display = "Mnemonic '{}' is already defined", _0)]
+    DuplicateMnemonic(&'static str),
+}
+
+/// An instruction decoded from memory
+pub struct Decoded<'a, C = i64> {
+    pub definition: &'a Definition<C>,
+    pub modes: Vec<ParameterMode>,
+}
+
+impl<C> Decoded<'_, C> {
+    /// The type and mode of each parameter, in the order they follow the opcode
+    pub fn parameters(&self) -> Vec<(ParameterType, ParameterMode)> {
+        self.definition
+            .parameters
+            .iter()
+            .copied()
+            .zip(self.modes.iter().copied())
+            .collect()
+    }
+
+    /// The number of memory cells the instruction occupies, including the opcode
+    pub fn size(&self) -> usize {
+        1 + self.modes.len()
+    }
+}
+
+/// The instructions a program can execute, by opcode
+#[derive(Clone)]
+pub struct InstructionSet<C = i64> {
+    definitions: BTreeMap<i64, Definition<C>>,
+}
+
+impl<C: Cell> InstructionSet<C> {
+    pub fn empty() -> Self {
+        InstructionSet {
+            definitions: BTreeMap::new(),
+        }
+    }
+
+    /// The instructions of the complete Intcode computer
+    pub fn standard() -> Self {
+        use ParameterType::{Read, Write};
+
+        let mut set = InstructionSet::empty();
+        let mut define =
+            |code, mnemonic, parameters: &[ParameterType], flow, execute: Executor<C>| {
+                set.register(Definition {
+                    code,
+                    mnemonic,
+                    parameters: parameters.to_vec(),
+                    flow,
+                    execute,
+                })
+                .expect("standard instructions are distinct");
+            };
+
+        define(
+            1,
+            "ADD",
+            &[Read, Read, Write],
+            Flow::Next,
+            |system, modes| instruction::add(system, modes).map(Some),
+        );
+        define(
+            2,
+            "MUL",
+            &[Read, Read, Write],
+            Flow::Next,
+            |system, modes| instruction::multiply(system, modes).map(Some),
+        );
+        define(3, "IN", &[Write], Flow::Input, |system, modes| {
+            instruction::store(system, modes[0]).map(Some)
+        });
+        define(4, "OUT", &[Read], Flow::Next, |system, modes| {
+            instruction::print(system, modes[0]).map(Some)
+        });
+        define(
+            5,
+            "JT",
+            &[Read, Read],
+            Flow::JumpIf(true),
+            |system, modes| instruction::jump_if(true, system, modes).map(Some),
+        );
+        define(
+            6,
+            "JF",
+            &[Read, Read],
+            Flow::JumpIf(false),
+            |system, modes| instruction::jump_if(false, system, modes).map(Some),
+        );
+        define(
+            7,
+            "LT",
+            &[Read, Read, Write],
+            Flow::Next,
+            |system, modes| instruction::less_than(system, modes).map(Some),
+        );
+        define(
+            8,
+            "EQ",
+            &[Read, Read, Write],
+            Flow::Next,
+            |system, modes| instruction::equals(system, modes).map(Some),
+        );
+        define(9, "ARB", &[Read], Flow::Next, |system, modes| {
+            instruction::adjust_relative_base(system, modes[0]).map(Some)
+        });
+        define(HALT, "HLT", &[], Flow::Halt, |_, _| Ok(None));
+
+        set
+    }
+
+    /// A shared copy of the standard instruction set for the cell type
+    pub fn shared_standard() -> Arc<Self> {
+        type Shared = Arc<dyn Any + Send + Sync>;
+        static STANDARD: OnceLock<Mutex<HashMap<TypeId, Shared>>> = OnceLock::new();
+
+        let mut sets = STANDARD.get_or_init(Default::default).lock().unwrap();
+        let set = sets
+            .entry(TypeId::of::<C>())
+            .or_insert_with(|| Arc::new(InstructionSet::<C>::standard()));
+        Arc::clone(set).downcast().unwrap()
+    }
+
+    /// Adds an instruction with an opcode and mnemonic that aren't defined yet
+    pub fn register(&mut self, definition: Definition<C>) -> Result<(), Error> {
+        if self.definitions.contains_key(&definition.code) {
+            return Err(Error::DuplicateCode(definition.code));
+        }
+        self.replace(definition).map(|_| ())
+    }
+
+    /// Adds an instruction, returning the one it replaces with the same opcode
+    pub fn replace(&mut self, definition: Definition<C>) -> Result<Option<Definition<C>>, Error> {
+        if !(1..100).contains(&definition.code) {
+            return Err(Error::InvalidCode(definition.code));
+        }
+        if self.definitions.values().any(|existing| {
+            existing.code != definition.code && existing.mnemonic == definition.mnemonic
+        }) {
+            return Err(Error::DuplicateMnemonic(definition.mnemonic));
+        }
+
+        Ok(self.definitions.insert(definition.code, definition))
+    }
+
+    pub fn get(&self, code: i64) -> Option<&Definition<C>> {
+        self.definitions.get(&code)
+    }
+
+    pub fn definitions(&self) -> impl Iterator<Item = &Definition<C>> {
+        self.definitions.values()
+    }
+
+    /// Decodes an instruction's opcode and parameter modes from its first cell
+    pub fn decode(&self, value: i64) -> Result<Decoded<'_, C>, ErrorKind> {
+        let definition = self
+            .definitions
+            .get(&(value % 100))
+            .ok_or(ErrorKind::InvalidOpcode)?;
+
+        let modes = definition
+            .parameters
+            .iter()
+            .enumerate()
+            .map(|(index, parameter)| {
+                let which = index + 1;
+                let digit = 10_i64
+                    .checked_pow(which as u32 + 1)
+                    .map_or(0, |place| (value / place) % 10);
+                match (digit, parameter) {
+                    (0, _) => Ok(ParameterMode::Position),
+                    (1, ParameterType::Read) => Ok(ParameterMode::Immediate),
+                    (2, _) => Ok(ParameterMode::Relative),
+                    _ => Err(ErrorKind::InvalidParameterMode(which)),
+                }
+            })
+            .collect::<Result<_, _>>()?;
+
+        Ok(Decoded { definition, modes })
+    }
+}
+
+impl<C: Cell> Default for InstructionSet<C> {
+    fn default() -> Self {
+        InstructionSet::standard()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::program::{Program, RunState, Stopped};
+    use super::*;
+
+    // ASRT a, b: fails unless a == b
+    fn assertion() -> Definition {
+        Definition {
+            code: 42,
+            mnemonic: "ASRT",
+            parameters: vec![ParameterType::Read, ParameterType::Read],
+            flow: Flow::Next,
+            execute: |system, modes| {
+                let operands = instruction::read_operands(
+                    system,
+                    &[
+                        (ParameterType::Read, modes[0]),
+                        (ParameterType::Read, modes[1]),
+                    ],
+                )?;
+                if operands[0] != operands[1] {
+                    return Err(ErrorKind::ExtensionFailed("assertion failed"));
+                }
+                Ok(Some(system.read_instruction_pointer() + 3))
+            },
+        }
+    }
+
+    // DBG a: outputs a
+    fn debug_print() -> Definition {
+        Definition {
+            code: 50,
+            mnemonic: "DBG",
+            parameters: vec![ParameterType::Read],
+            flow: Flow::Next,
+            execute: |system, modes| {
+                let operands =
+                    instruction::read_operands(system, &[(ParameterType::Read, modes[0])])?;
+                system.write_output(operands[0]);
+                Ok(Some(system.read_instruction_pointer() + 2))
+            },
+        }
+    }
+
+    fn extended() -> InstructionSet {
+        let mut set = InstructionSet::standard();
+        set.register(assertion()).unwrap();
+        set.register(debug_print()).unwrap();
+        set
+    }
+
+    fn decode(value: i64) -> Result<(&'static str, Vec<ParameterMode>), ErrorKind> {
+        let set: InstructionSet = InstructionSet::standard();
+        set.decode(value)
+            .map(|decoded| (decoded.definition.mnemonic, decoded.modes))
+    }
+
+    #[test]
+    fn decodes_addition() {
+        use ParameterMode::{Immediate, Position};
+        assert_eq!(Ok(("ADD", vec![Position, Position, Position])), decode(1));
+        assert_eq!(
+            Ok(("ADD", vec![Immediate, Position, Position])),
+            decode(101)
+        );
+        assert_eq!(
+            Ok(("ADD", vec![Position, Immediate, Position])),
+            decode(1001)
+        );
+        assert_eq!(
+            Ok(("ADD", vec![Immediate, Immediate, Position])),
+            decode(1101)
+        );
+    }
+
+    #[test]
+    fn decodes_multiplication() {
+        use ParameterMode::{Immediate, Position};
+        assert_eq!(Ok(("MUL", vec![Position, Position, Position])), decode(2));
+        assert_eq!(
+            Ok(("MUL", vec![Immediate, Position, Position])),
+            decode(102)
+        );
+        assert_eq!(
+            Ok(("MUL", vec![Position, Immediate, Position])),
+            decode(1002)
+        );
+        assert_eq!(
+            Ok(("MUL", vec![Immediate, Immediate, Position])),
+            decode(1102)
+        );
+    }
+
+    #[test]
+    fn decodes_relative_mode_parameters() {
+        use ParameterMode::{Immediate, Relative};
+        assert_eq!(
+            Ok(("ADD", vec![Relative, Immediate, Relative])),
+            decode(21201)
+        );
+    }
+
+    #[test]
+    fn decodes_input_and_output() {
+        use ParameterMode::{Immediate, Position, Relative};
+        assert_eq!(Ok(("IN", vec![Position])), decode(3));
+        assert_eq!(Ok(("IN", vec![Relative])), decode(203));
+        assert_eq!(Ok(("OUT", vec![Position])), decode(4));
+        assert_eq!(Ok(("OUT", vec![Immediate])), decode(104));
+    }
+
+    #[test]
+    fn decodes_jumps_and_comparisons() {
+        use ParameterMode::{Immediate, Position, Relative};
+        assert_eq!(Ok(("JT", vec![Immediate, Immediate])), decode(1105));
+        assert_eq!(Ok(("JF", vec![Position, Immediate])), decode(1006));
+        assert_eq!(
+            Ok(("LT", vec![Immediate, Immediate, Relative])),
+            decode(21107)
+        );
+        assert_eq!(Ok(("EQ", vec![Position, Position, Position])), decode(8));
+    }
+
+    #[test]
+    fn decodes_adjust_relative_base() {
+        use ParameterMode::{Immediate, Position, Relative};
+        assert_eq!(Ok(("ARB", vec![Position])), decode(9));
+        assert_eq!(Ok(("ARB", vec![Immediate])), decode(109));
+        assert_eq!(Ok(("ARB", vec![Relative])), decode(209));
+    }
+
+    #[test]
+    fn decodes_halt() {
+        assert_eq!(Ok(("HLT", vec![])), decode(99));
+    }
+
+    #[test]
+    fn reports_parameters_in_order() {
+        let set: InstructionSet = InstructionSet::standard();
+        let decoded = set.decode(2107).unwrap();
+        assert_eq!(
+            vec![
+                (ParameterType::Read, ParameterMode::Immediate),
+                (ParameterType::Read, ParameterMode::Relative),
+                (ParameterType::Write, ParameterMode::Position),
+            ],
+            decoded.parameters()
+        );
+        assert_eq!(4, decoded.size());
+        assert_eq!(1, set.decode(99).unwrap().size());
+    }
+
+    #[test]
+    fn decoding_fails_for_unknown_opcodes() {
+        for value in &[0, 5555, -1] {
+            assert_eq!(Err(ErrorKind::InvalidOpcode), decode(*value));
+        }
+    }
+
+    #[test]
+    fn decoding_fails_when_write_parameter_is_in_immediate_mode() {
+        assert_eq!(Err(ErrorKind::InvalidParameterMode(3)), decode(11101));
+        assert_eq!(Err(ErrorKind::InvalidParameterMode(1)), decode(103));
+        assert_eq!(Err(ErrorKind::InvalidParameterMode(1)), decode(11103));
+    }
+
+    #[test]
+    fn decoding_fails_when_parameter_mode_is_unknown() {
+        assert_eq!(Err(ErrorKind::InvalidParameterMode(2)), decode(3001));
+        assert_eq!(Err(ErrorKind::InvalidParameterMode(3)), decode(30001));
+        assert_eq!(Err(ErrorKind::InvalidParameterMode(1)), decode(301));
+    }
+
+    #[test]
+    fn an_empty_set_decodes_nothing() {
+        assert_eq!(
+            Some(ErrorKind::InvalidOpcode),
+            InstructionSet::<i64>::empty().decode(99).err()
+        );
+    }
+
+    #[test]
+    fn replacing_returns_the_existing_instruction() {
+        let mut set = InstructionSet::standard();
+        let replaced = set.replace(Definition {
+            code: 4,
+            ..debug_print()
+        });
+        assert_eq!(
+            Some("OUT"),
+            replaced.unwrap().map(|definition| definition.mnemonic)
+        );
+        assert_eq!("DBG", set.get(4).unwrap().mnemonic);
+    }
+
+    #[test]
+    fn rejects_duplicate_opcodes_and_mnemonics() {
+        let mut set = InstructionSet::standard();
+        assert_eq!(
+            Some(Error::DuplicateCode(4)),
+            set.register(Definition {
+                code: 4,
+                ..debug_print()
+            })
+            .err()
+        );
+        assert_eq!(
+            Some(Error::DuplicateMnemonic("OUT")),
+            set.replace(Definition {
+                mnemonic: "OUT",
+                ..debug_print()
+            })
+            .err()
+        );
+        assert_eq!("OUT", set.get(4).unwrap().mnemonic);
+        assert!(set.get(50).is_none());
+    }
+
+    #[test]
+    fn rejects_opcodes_that_dont_fit() {
+        for code in &[0, 100, -1] {
+            assert_eq!(
+                Some(Error::InvalidCode(*code)),
+                InstructionSet::empty()
+                    .register(Definition {
+                        code: *code,
+                        ..assertion()
+                    })
+                    .err()
+            );
+        }
+    }
+
+    #[test]
+    fn runs_registered_instructions() {
+        let memory = [1101, 2, 3, 10, 42, 10, 11, 50, 10, 99, 0, 5];
+        let mut program = Program::new(&memory);
+        program.set_instruction_set(extended());
+        assert_eq!(Ok(RunState::Output(5)), program.run_until_blocked());
+        assert_eq!(Ok(RunState::Halted), program.run_until_blocked());
+        assert_eq!(
+            vec!["ADD", "ASRT", "DBG", "HLT"],
+            program
+                .instruction_set()
+                .definitions()
+                .filter(|d| [1, 42, 50, 99].contains(&d.code))
+                .map(|d| d.mnemonic)
+                .collect::<Vec<_>>()
+        );
+    }
+
+    #[test]
+    fn reports_errors_from_registered_instructions() {
+        let memory = [42, 0, 1, 99];
+        let mut program = Program::new(&memory);
+        program.set_instruction_set(extended());
+        let error = program.run().unwrap_err();
+        assert_eq!(ErrorKind::ExtensionFailed("assertion failed"), error.kind);
+        assert_eq!(Some(0), error.address);
+
+        let mut program = Program::new(&memory);
+        assert_eq!(ErrorKind::InvalidOpcode, program.run().unwrap_err().kind);
+    }
+
+    #[test]
+    fn needs_input_for_registered_input_instructions() {
+        let mut set = InstructionSet::standard();
+        set.register(Definition {
+            code: 42,
+            mnemonic: "READ",
+            parameters: vec![ParameterType::Write],
+            flow: Flow::Input,
+            execute: |system, modes| instruction::store(system, modes[0]).map(Some),
+        })
+        .unwrap();
+
+        let mut program = Program::new(&[42, 0, 99]);
+        assert!(!program.needs_input());
+        program.set_instruction_set(set);
+        assert!(program.needs_input());
+        program.push_input(7);
+        assert!(!program.needs_input());
+    }
+
+    #[test]
+    fn custom_halt_instructions_stop_the_program() {
+        let mut set = InstructionSet::empty();
+        set.register(Definition {
+            code: 1,
+            mnemonic: "STOP",
+            parameters: vec![],
+            flow: Flow::Halt,
+            execute: |_, _| Ok(None),
+        })
+        .unwrap();
+        let mut program = Program::new(&[1]);
+        program.set_instruction_set(set);
+        program.set_step_limit(Some(0));
+        assert_eq!(Ok(Stopped::Halted), program.run());
+    }
+
+    #[test]
+    fn step_limit_applies_to_a_replaced_halt_instruction() {
+        let mut set = InstructionSet::standard();
+        set.replace(Definition {
+            code: HALT,
+            mnemonic: "SPIN",
+            parameters: vec![],
+            flow: Flow::Unknown,
+            execute: |system, _| Ok(Some(system.read_instruction_pointer())),
+        })
+        .unwrap();
+        let mut program = Program::new(&[99]);
+        program.set_instruction_set(set);
+        program.set_step_limit(Some(10));
+        assert_eq!(
+            ErrorKind::StepLimitExceeded,
+            program.run().unwrap_err().kind
+        );
+    }
+}
@@ -0,0 +1,165 @@
+use super::image::Image;
+use super::program::{Stopped, System};
+use std::convert::TryFrom;
+use std::ops::RangeInclusive;
+use std::thread;
+
+/// Candidates that execute more instructions than this are treated as
+/// non-solutions, so one that loops forever can't stall the search
+pub const STEP_LIMIT: u64 = 1_000_000;
+
+#[derive(Clone, Copy, Debug, Eq, Fail, PartialEq)]
+pub enum Error {
+    #[fail(display = "The search space has too many candidates to count")]
+    SearchSpaceTooLarge,
+    #[fail(display = "Input address {} is outside the program's memory", _0)]
+    InputOutOfRange(usize),
+}
+
+/// Searches for values of the `inputs` addresses that make the program halt with
+/// `target` at `output_address`, trying every combination of the given ranges.
+/// Returns the solutions as values in the same order as `inputs`, ordered as if
+/// the last input varied fastest. Candidates that fail with an error, wait for
+/// input or exceed `STEP_LIMIT` aren't solutions. Fails if an input address
+/// lies outside `memory`.
+pub fn solve(
+    memory: &[i64],
+    inputs: &[(usize, RangeInclusive<i64>)],
+    output_address: usize,
+    target: i64,
+) -> Result<Vec<Vec<i64>>, Error> {
+    if let Some((address, _)) = inputs.iter().find(|(address, _)| *address >= memory.len()) {
+        return Err(Error::InputOutOfRange(*address));
+    }
+
+    let sizes = inputs
+        .iter()
+        .map(|(_, range)| {
+            let size = (i128::from(*range.end()) - i128::from(*range.start()) + 1).max(0);
+            u64::try_from(size).map_err(|_| Error::SearchSpaceTooLarge)
+        })
+        .collect::<Result<Vec<_>, _>>()?;
+    let total = sizes.iter().try_fold(1_u64, |total, size| {
+        total.checked_mul(*size).ok_or(Error::SearchSpaceTooLarge)
+    })?;
+    let image = Image::new(memory);
+    let threads = thread::available_parallelism().map_or(1, |n| n.get()) as u64;
+    let chunk = total.div_ceil(threads).max(1);
+
+    thread::scope(|scope| {
+        let workers: Vec<_> = (0..total)
+            .step_by(chunk as usize)
+            .map(|start| {
+                let (image, sizes) = (&image, &sizes);
+                scope.spawn(move || {
+                    (start..total.min(start + chunk))
+                        .map(|index| candidate(inputs, sizes, index))
+                        .filter(|values| produces(image, inputs, values, output_address, target))
+                        .collect::<Vec<_>>()
+                })
+            })
+            .collect();
+
+        Ok(workers
+            .into_iter()
+            .flat_map(|worker| worker.join().unwrap())
+            .collect())
+    })
+}
+
+/// Decodes the `index`th combination of input values
+fn candidate(inputs: &[(usize, RangeInclusive<i64>)], sizes: &[u64], mut index: u64) -> Vec<i64> {
+    let mut values = vec![0; inputs.len()];
+    for (i, (_, range)) in inputs.iter().enumerate().rev() {
+        values[i] = range.start() + (index % sizes[i]) as i64;
+        index /= sizes[i];
+    }
+    values
+}
+
+fn produces(
+    image: &Image,
+    inputs: &[(usize, RangeInclusive<i64>)],
+    values: &[i64],
+    output_address: usize,
+    target: i64,
+) -> bool {
+    let patches: Vec<_> = inputs
+        .iter()
+        .zip(values)
+        .map(|((address, _), value)| (*address, *value))
+        .collect();
+    let mut program = image
+        .program(&patches)
+        .expect("input addresses are checked before the search");
+    program.set_step_limit(Some(STEP_LIMIT));
+
+    program.run() == Ok(Stopped::Halted) && program.read_memory(output_address) == Ok(target)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Adds the values at [1] and [2] into [0]
+    const ADDER: [i64; 5] = [1101, 0, 0, 0, 99];
+
+    #[test]
+    fn finds_every_solution_in_order() {
+        assert_eq!(
+            vec![vec![1, 4], vec![2, 3], vec![3, 2], vec![4, 1]],
+            solve(&ADDER, &[(1, 1..=4), (2, 1..=4)], 0, 5).unwrap()
+        );
+    }
+
+    #[test]
+    fn returns_nothing_when_no_candidate_matches() {
+        assert_eq!(Ok(vec![]), solve(&ADDER, &[(1, 0..=4), (2, 0..=4)], 0, 500));
+    }
+
+    #[test]
+    fn runs_the_unpatched_program_when_there_are_no_inputs() {
+        assert_eq!(
+            Ok(vec![Vec::<i64>::new()]),
+            solve(&[1101, 2, 3, 0, 99], &[], 0, 5)
+        );
+    }
+
+    #[test]
+    fn returns_nothing_for_an_empty_range() {
+        #[allow(clippy::reversed_empty_ranges)]
+        let empty = 4..=1;
+        assert_eq!(Ok(vec![]), solve(&ADDER, &[(1, empty), (2, 0..=4)], 0, 1));
+    }
+
+    #[test]
+    fn rejects_input_addresses_outside_memory() {
+        assert_eq!(
+            Err(Error::InputOutOfRange(50)),
+            solve(&ADDER, &[(1, 0..=3), (50, 0..=3)], 0, 1)
+        );
+    }
+
+    #[test]
+    fn rejects_search_spaces_too_large_to_count() {
+        assert_eq!(
+            Err(Error::SearchSpaceTooLarge),
+            solve(&ADDER, &[(1, i64::MIN..=i64::MAX)], 0, 1)
+        );
+        assert_eq!(
+            Err(Error::SearchSpaceTooLarge),
+            solve(&ADDER, &[(1, 0..=i64::MAX), (2, 0..=2)], 0, 1)
+        );
+    }
+
+    #[test]
+    fn treats_failing_and_looping_candidates_as_non_solutions() {
+        // Each candidate leaves the target at [0], but only one halts
+        assert_eq!(Ok(vec![]), solve(&[0, 99], &[(0, 42..=42)], 0, 42));
+        assert_eq!(Ok(vec![]), solve(&[0, 0, 99], &[(0, 3..=3)], 0, 3));
+        assert_eq!(
+            Ok(vec![vec![0]]),
+            solve(&[1105, 0, 0, 99], &[(1, 0..=1)], 0, 1105)
+        );
+    }
+}
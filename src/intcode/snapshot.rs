@@ -0,0 +1,126 @@
+use bincode::Options;
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+
+/// The execution state of a program, as captured by `Program::snapshot`.
+/// Breakpoints, watchpoints, the step limit, the tracer and the I/O channels
+/// belong to the program rather than its state, so they aren't included.
+#[derive(Clone, Debug, Deserialize, Eq, PartialEq, Serialize)]
+pub struct Snapshot<C = i64> {
+    pub memory: Vec<C>,
+    pub instruction_pointer: usize,
+    pub relative_base: i64,
+    /// Values queued with `push_input` that haven't been read yet. Output is
+    /// delivered as it's produced, so none is ever pending.
+    pub queued_input: Vec<C>,
+    pub steps: u64,
+    pub patches: Vec<(usize, C)>,
+}
+
+#[derive(Debug, Fail)]
+pub enum Error {
+    #[fail(display = "Failed to encode or decode a binary snapshot")]
+    Binary(#[cause] bincode::Error),
+    #[fail(display = "Failed to encode or decode a JSON snapshot")]
+    Json(#[cause] serde_json::Error),
+    #[fail(
+        display = "Snapshot instruction pointer {} is beyond its {} memory cells",
+        _0, _1
+    )]
+    InstructionPointerOutOfRange(usize, usize),
+    #[fail(
+        display = "Snapshot has {} memory cells, more than a program can hold",
+        _0
+    )]
+    MemoryTooLarge(usize),
+}
+
+impl<C: DeserializeOwned + Serialize> Snapshot<C> {
+    /// Encodes the snapshot in a compact binary format
+    pub fn to_bytes(&self) -> Vec<u8> {
+        // Serializing plain integers and vectors can't fail
+        encoding().serialize(self).unwrap()
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, Error> {
+        encoding().deserialize(bytes).map_err(Error::Binary)
+    }
+
+    pub fn to_json(&self) -> String {
+        serde_json::to_string(self).unwrap()
+    }
+
+    pub fn from_json(json: &str) -> Result<Self, Error> {
+        serde_json::from_str(json).map_err(Error::Json)
+    }
+}
+
+fn encoding() -> impl Options {
+    bincode::DefaultOptions::new().reject_trailing_bytes()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn snapshot() -> Snapshot {
+        Snapshot {
+            memory: vec![3, 0, 4, 0, 99, -123_456_789_000],
+            instruction_pointer: 2,
+            relative_base: -7,
+            queued_input: vec![5, 6],
+            steps: 1,
+            patches: vec![(5, -123_456_789_000)],
+        }
+    }
+
+    #[test]
+    fn round_trips_through_binary() {
+        let bytes = snapshot().to_bytes();
+        assert_eq!(snapshot(), Snapshot::from_bytes(&bytes).unwrap());
+    }
+
+    #[test]
+    fn binary_encoding_is_compact() {
+        // Small values take a single byte each
+        let snapshot = Snapshot {
+            memory: vec![1, 0, 0, 0, 99],
+            patches: vec![],
+            ..snapshot()
+        };
+        assert_eq!(13, snapshot.to_bytes().len());
+    }
+
+    #[test]
+    fn round_trips_through_json() {
+        let json = snapshot().to_json();
+        assert!(json.contains("\"instruction_pointer\":2"));
+        assert_eq!(snapshot(), Snapshot::from_json(&json).unwrap());
+    }
+
+    #[test]
+    fn round_trips_other_cell_types() {
+        let snapshot = Snapshot {
+            memory: vec![i128::MAX, -1],
+            instruction_pointer: 0,
+            relative_base: 0,
+            queued_input: vec![i128::MIN],
+            steps: 0,
+            patches: vec![],
+        };
+        let bytes = snapshot.to_bytes();
+        assert_eq!(snapshot, Snapshot::from_bytes(&bytes).unwrap());
+    }
+
+    #[test]
+    fn rejects_malformed_encodings() {
+        let bytes = snapshot().to_bytes();
+        assert!(matches!(
+            Snapshot::<i64>::from_bytes(&bytes[..bytes.len() - 1]),
+            Err(Error::Binary(_))
+        ));
+        assert!(matches!(
+            Snapshot::<i64>::from_json("{\"memory\": [1]}"),
+            Err(Error::Json(_))
+        ));
+    }
+}
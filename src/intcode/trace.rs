@@ -0,0 +1,113 @@
+use super::cell::Cell;
+use super::disassembler;
+use super::instruction::{ParameterMode, ParameterType};
+use std::io;
+
+/// Describes one executed instruction
+#[derive(Debug)]
+pub struct TraceEvent<'a, C = i64> {
+    pub address: usize,
+    pub mnemonic: &'a str,
+    /// The type and mode of each parameter
+    pub parameters: &'a [(ParameterType, ParameterMode)],
+    /// The raw parameter values that follow the opcode in memory
+    pub params: &'a [C],
+    /// The resolved values of the read parameters, in order
+    pub operands: &'a [C],
+    /// Each memory write the instruction made, as `(address, value)`
+    pub writes: &'a [(usize, C)],
+}
+
+pub trait Tracer<C = i64> {
+    fn trace(&mut self, event: &TraceEvent<C>);
+}
+
+impl<C, F> Tracer<C> for F
+where
+    F: FnMut(&TraceEvent<C>),
+{
+    fn trace(&mut self, event: &TraceEvent<C>) {
+        self(event)
+    }
+}
+
+/// Writes one line per instruction, e.g.
+/// `0012: ADD [15], #3 -> [20] ; in 4, 3 ; [20] <- 7`
+pub struct TextTracer<W: io::Write> {
+    writer: W,
+}
+
+impl<W: io::Write> TextTracer<W> {
+    pub fn new(writer: W) -> Self {
+        TextTracer { writer }
+    }
+
+    pub fn into_inner(self) -> W {
+        self.writer
+    }
+}
+
+impl<C: Cell, W: io::Write> Tracer<C> for TextTracer<W> {
+    fn trace(&mut self, event: &TraceEvent<C>) {
+        let mut line = format!(
+            "{:04}: {}",
+            event.address,
+            disassembler::format_parts(event.mnemonic, event.parameters, event.params)
+        );
+
+        if !event.operands.is_empty() {
+            let operands: Vec<_> = event.operands.iter().map(C::to_string).collect();
+            line.push_str(" ; in ");
+            line.push_str(&operands.join(", "));
+        }
+
+        for (address, value) in event.writes {
+            line.push_str(&format!(" ; [{}] <- {}", address, value));
+        }
+
+        // A trace is a diagnostic aid, so a failing writer shouldn't stop the program
+        let _ = writeln!(self.writer, "{}", line);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::registry::InstructionSet;
+    use super::*;
+
+    fn trace_text(event: &TraceEvent) -> String {
+        let mut tracer = TextTracer::new(Vec::<u8>::new());
+        tracer.trace(event);
+        String::from_utf8(tracer.into_inner()).unwrap()
+    }
+
+    #[test]
+    fn text_tracer_writes_operands_and_memory_writes() {
+        let set: InstructionSet = InstructionSet::standard();
+        let event = TraceEvent {
+            address: 12,
+            mnemonic: "ADD",
+            parameters: &set.decode(1001).unwrap().parameters(),
+            params: &[15, 3, 20],
+            operands: &[4, 3],
+            writes: &[(20, 7)],
+        };
+        assert_eq!(
+            "0012: ADD [15], #3 -> [20] ; in 4, 3 ; [20] <- 7\n",
+            trace_text(&event)
+        );
+    }
+
+    #[test]
+    fn text_tracer_omits_empty_sections() {
+        let event = TraceEvent {
+            address: 4,
+            mnemonic: "HLT",
+            parameters: &[],
+            params: &[],
+            operands: &[],
+            writes: &[],
+        };
+        assert_eq!("0004: HLT\n", trace_text(&event));
+    }
+}
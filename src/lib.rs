@@ -1,3 +1,5 @@
+#![allow(non_local_definitions)] // emitted by the `Fail` derive
+
 #[macro_use]
 extern crate failure_derive;
 